@@ -1,5 +1,5 @@
 use aunty::{delegate, Entity, Obj};
-use giaw_shared::game::actors::inventory::ItemStackBase;
+use giaw_shared::game::actors::inventory::{InteractMode, ItemStackBase};
 use macroquad::{color::Color, math::Vec2};
 
 #[derive(Debug, Clone)]
@@ -16,9 +16,3 @@ delegate! {
         world_to: Vec2,
     )
 }
-
-#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
-pub enum InteractMode {
-    Build,
-    Break,
-}
@@ -1,33 +1,57 @@
-use std::ops::ControlFlow;
+use std::{collections::VecDeque, ops::ControlFlow, time::Duration};
 
-use aunty::{make_extensible, CyclicCtor, Entity, Obj};
+use aunty::{autoken::ImmutableBorrow, make_extensible, CyclicCtor, Entity, Obj};
 use giaw_shared::{
-    game::actors::{inventory::InventoryData, player::PlayerState},
+    game::actors::{
+        inventory::{
+            BlockItemDescriptor, InteractMode, InventoryData, ItemStackBase, LaunchItemDescriptor,
+        },
+        player::{
+            PlayerInput, PlayerLaunch, PlayerMotion, PlayerRpcs, PlayerState, PlayerUseItem,
+            PlayerUseItemReply, MAX_TILES_PER_USE,
+        },
+    },
     util::{
         game::{
             actors::{ActorManager, DespawnHandler, UpdateHandler},
-            rpc::{ClientRpcNode, RpcNodeId},
-            tile::{TileLayerConfig, TileMap},
+            rpc::{ClientRpcNode, ClientRpcNodeSender, RpcAuthority, RpcCall, RpcNodeId},
+            tile::{LayerIndex, MaterialInfo, TileLayerConfig, TileMap},
             transform::{Collider, EntityExt, Transform},
         },
         math::{aabb::Aabb, scalar::lerp_f32},
     },
 };
 use macroquad::{
-    color::{BLUE, RED},
+    color::{BLUE, ORANGE, RED},
     input::{is_key_down, is_key_pressed, is_mouse_button_down, mouse_position},
     math::{IVec2, Vec2},
     miniquad::{KeyCode, MouseButton},
     shapes::{draw_circle, draw_rectangle},
-    time::get_frame_time,
 };
 
 use crate::{
     engine::scene::RenderHandler,
-    game::services::camera::{CameraManager, VirtualCamera, VirtualCameraConstraints},
+    game::services::{
+        camera::{CameraManager, VirtualCamera, VirtualCameraConstraints},
+        clock::ClientClock,
+        interpolation::{InterpolationConfig, PoseSnapshot, SnapshotInterpolator},
+    },
 };
 
-use super::inventory::{ClientItemUseHandler, InteractMode};
+use super::inventory::ClientItemUseHandler;
+
+// How long we wait for the server to confirm a predicted tile edit before rolling it back.
+const USE_ITEM_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The number of unacknowledged inputs we keep around for replay. Older inputs are forgotten, which
+// only matters if the server stops acknowledging our inputs altogether.
+const MAX_PENDING_INPUTS: usize = 256;
+
+// How quickly prediction errors are blended out of the rendered position, in 1/seconds.
+const CORRECTION_RATE: f32 = 10.;
+
+// Prediction errors larger than this are snapped to rather than blended out.
+const MAX_CORRECTION: f32 = 2.;
 
 // === Components === //
 
@@ -35,6 +59,36 @@ use super::inventory::{ClientItemUseHandler, InteractMode};
 pub struct ClientPlayerState {
     last_interact_pos: Vec2,
     last_interact_mode: Option<InteractMode>,
+    pending_edits: Vec<PendingTileEdit>,
+
+    // Movement prediction
+    next_input: u32,
+    pending_inputs: VecDeque<PlayerInput>,
+    queued_launch: Option<Vec2>,
+    correction: Vec2,
+    latest_motion: Option<f64>,
+}
+
+#[derive(Debug)]
+struct PendingTileEdit {
+    call: RpcCall<PlayerUseItemReply>,
+    layer: LayerIndex,
+    previous: Vec<(IVec2, MaterialInfo)>,
+
+    // The stack we predicted using and how many items each edited tile added to it. Building takes
+    // an item and breaking gives one back.
+    stack: Obj<ItemStackBase>,
+    items_per_tile: i32,
+}
+
+impl PendingTileEdit {
+    // Undoes the effect which `tiles` of our edits had on the stack.
+    fn revert_items(&self, tiles: usize) {
+        let mut stack = self.stack.get_mut();
+        stack.count = stack
+            .count
+            .saturating_add_signed(-self.items_per_tile * tiles as i32);
+    }
 }
 
 #[derive(Debug)]
@@ -46,6 +100,8 @@ pub struct ClientPlayerDriver {
     client_state: Obj<ClientPlayerState>,
     camera: Obj<VirtualCamera>,
     inventory: Obj<InventoryData>,
+    use_item_sender: ClientRpcNodeSender,
+    input_sender: ClientRpcNodeSender,
 
     // Deep dependencies
     camera_mgr: Obj<CameraManager>,
@@ -56,20 +112,47 @@ make_extensible!(pub ClientPlayerDriverObj for ClientPlayerDriver);
 
 impl ClientPlayerDriver {
     pub fn new() -> impl CyclicCtor<Self> {
-        |me, _| Self {
-            me,
-            xform: me.obj(),
-            state: me.obj(),
-            client_state: me.obj(),
-            camera: me.obj(),
-            inventory: me.obj(),
-            camera_mgr: me.deep_obj(),
-            tile_map: me.deep_obj(),
+        |me, _| {
+            let node = me.obj::<ClientRpcNode>();
+
+            node.builder().sub(PlayerRpcs::Motion).bind_message(
+                RpcAuthority::ServerOnly,
+                move |(), _target, motion: PlayerMotion| {
+                    me.get::<ClientPlayerDriver>().reconcile(&motion);
+                    Ok(())
+                },
+            );
+
+            // Start off wherever the server says we are.
+            if let Ok(motion) = node
+                .builder()
+                .sub(PlayerRpcs::Motion)
+                .read_catchup::<PlayerMotion>()
+            {
+                me.get_mut::<PlayerState>().set_motion(&motion);
+                me.get_mut::<ClientPlayerState>().latest_motion = Some(motion.time);
+            }
+
+            Self {
+                me,
+                xform: me.obj(),
+                state: me.obj(),
+                client_state: me.obj(),
+                camera: me.obj(),
+                inventory: me.obj(),
+                use_item_sender: node.builder().sub(PlayerRpcs::UseItem).sender(),
+                // Inputs stay reliable since the server has to simulate every one of them for our
+                // predictions to line up with its own.
+                input_sender: node.builder().sub(PlayerRpcs::Input).sender(),
+                camera_mgr: me.deep_obj(),
+                tile_map: me.deep_obj(),
+            }
         }
     }
 
-    pub fn update(&self) {
-        let dt = get_frame_time();
+    pub fn update(&self, dt: f32) {
+        // Reconcile our predicted tile edits with the server's verdicts
+        self.resolve_tile_edits();
 
         // Handle inventory selection
         {
@@ -133,38 +216,119 @@ impl ClientPlayerDriver {
                 break 'interact;
             };
 
-            item_material
-                .get::<ClientItemUseHandler>()
-                .call(self.me, item, mode, last_pos, curr_pos);
+            // Tile edits are predicted locally and validated by the server. Launches are applied as
+            // part of our next movement input.
+            let loaner = ImmutableBorrow::new();
+            let block = item_material
+                .try_get::<BlockItemDescriptor>(&loaner)
+                .map(|block| block.material);
+
+            if let Some(block) = block {
+                self.use_block_item(hotbar_slot, block, mode, last_pos, curr_pos);
+            } else if item_material
+                .try_get::<LaunchItemDescriptor>(&loaner)
+                .is_some()
+            {
+                self.client_state.get_mut().queued_launch = Some(curr_pos);
+            } else if let Some(handler) = item_material.try_get::<ClientItemUseHandler>(&loaner) {
+                handler.call(self.me, item, mode, last_pos, curr_pos);
+            }
         }
 
         // Handle motion
         {
-            let mut player = self.state.get_mut();
-            let mut heading = 0.;
-            let magnitude = 5.;
+            let mut client_state = self.client_state.get_mut();
+            let mut walk = 0;
 
             if is_key_down(KeyCode::A) {
-                heading = -magnitude;
+                walk = -1;
             }
 
             if is_key_down(KeyCode::D) {
-                heading = magnitude;
+                walk = 1;
             }
 
-            player.velocity.x = (player.velocity.x + heading) / 2.;
+            let input = PlayerInput {
+                seq: client_state.next_input,
+                dt,
+                walk,
+                jump: is_key_down(KeyCode::Space),
+                launch: client_state
+                    .queued_launch
+                    .take()
+                    .map(|target| PlayerLaunch {
+                        slot: self.state.get().hotbar_slot as u32,
+                        target: target.into(),
+                    }),
+            };
+
+            // Predict the input's outcome and send it off to the server, which will eventually tell
+            // us where we actually ended up.
+            if self.state.get_mut().apply_input(&input).is_ok() {
+                client_state.next_input += 1;
+                self.input_sender.send((), &input);
+                client_state.pending_inputs.push_back(input);
 
-            if is_key_down(KeyCode::Space) && player.is_on_ground() {
-                player.velocity.y = -10.;
+                if client_state.pending_inputs.len() > MAX_PENDING_INPUTS {
+                    client_state.pending_inputs.pop_front();
+                }
             }
 
-            player.update(dt);
+            // Blend out prediction errors
+            client_state.correction = client_state
+                .correction
+                .lerp(Vec2::ZERO, (dt * CORRECTION_RATE).min(1.));
         }
     }
 
+    fn reconcile(&self, motion: &PlayerMotion) {
+        let mut client_state = self.client_state.get_mut();
+
+        // Motion is sent unreliably so we can still receive updates older than our catchup, e.g.
+        // ones which were deferred until we spawned.
+        if client_state
+            .latest_motion
+            .is_some_and(|latest| motion.time < latest)
+        {
+            return;
+        }
+        client_state.latest_motion = Some(motion.time);
+
+        // Forget the inputs which the server has already simulated...
+        if let Some(last_input) = motion.last_input {
+            while client_state
+                .pending_inputs
+                .front()
+                .is_some_and(|input| input.seq <= last_input)
+            {
+                client_state.pending_inputs.pop_front();
+            }
+        }
+
+        // ...rewind to the authoritative state...
+        let predicted = self.xform.get().global_pos();
+        let mut state = self.state.get_mut();
+        state.set_motion(motion);
+
+        // ...and replay the inputs it hasn't seen yet on top of it.
+        for input in &client_state.pending_inputs {
+            // (these inputs were already validated when we first predicted them)
+            let _ = state.apply_input(input);
+        }
+
+        drop(state);
+
+        // Small errors are blended out over the next few frames so that the player doesn't jitter.
+        let correction = client_state.correction + predicted - self.xform.get().global_pos();
+        client_state.correction = if correction.length() <= MAX_CORRECTION {
+            correction
+        } else {
+            Vec2::ZERO
+        };
+    }
+
     pub fn render(&self) {
-        let xform = self.xform.get();
-        let pos = xform.global_pos();
+        let pos = self.xform.get().global_pos() + self.client_state.get().correction;
 
         // FOV change
         {
@@ -191,6 +355,130 @@ impl ClientPlayerDriver {
         draw_circle(pos.x, pos.y, 0.3, RED);
     }
 
+    fn use_block_item(
+        &self,
+        slot: usize,
+        block: MaterialInfo,
+        mode: InteractMode,
+        from: Vec2,
+        to: Vec2,
+    ) {
+        let mut tile_map = self.tile_map.get_mut();
+        let layer = tile_map.layer("under_player");
+        let layer_config = tile_map.layer_config(layer);
+        let air = tile_map.materials.get().get_by_name("air");
+        let material = match mode {
+            InteractMode::Build => block,
+            InteractMode::Break => air,
+        };
+
+        let Some(stack) = self.inventory.get().stacks()[slot].clone() else {
+            return;
+        };
+
+        // Every tile we build uses up one of the stack's items.
+        let budget = match mode {
+            InteractMode::Build => (stack.get().count as usize).min(MAX_TILES_PER_USE),
+            InteractMode::Break => MAX_TILES_PER_USE,
+        };
+
+        // Apply the edit locally, skipping the tiles which the server would reject outright.
+        let mut tiles = Vec::new();
+        let mut previous = Vec::new();
+
+        cbit::cbit!(for pos in self.selected_tiles(layer_config, from, to) {
+            let current = tile_map.get(layer, pos);
+            let is_air = current.id == air.id;
+            let applicable = match mode {
+                InteractMode::Build => is_air,
+                InteractMode::Break => !is_air,
+            };
+
+            if applicable && tiles.len() < budget {
+                tile_map.set(layer, pos, material);
+                tiles.push((pos.x, pos.y));
+                previous.push((pos, current));
+            }
+        });
+
+        drop(tile_map);
+
+        if tiles.is_empty() {
+            return;
+        }
+
+        // Every tile we build uses up one of the stack's items and every tile we break gives one
+        // back, just like on the server.
+        let items_per_tile = match mode {
+            InteractMode::Build => -1,
+            InteractMode::Break => 1,
+        };
+        {
+            let mut stack = stack.get_mut();
+            stack.count = stack
+                .count
+                .saturating_add_signed(items_per_tile * tiles.len() as i32);
+        }
+
+        // Ask the server to confirm it
+        let call = self.use_item_sender.call(
+            (),
+            &PlayerUseItem {
+                slot: slot as u32,
+                mode,
+                tiles,
+            },
+            USE_ITEM_TIMEOUT,
+        );
+
+        self.client_state
+            .get_mut()
+            .pending_edits
+            .push(PendingTileEdit {
+                call,
+                layer,
+                previous,
+                stack,
+                items_per_tile,
+            });
+    }
+
+    fn resolve_tile_edits(&self) {
+        let mut client_state = self.client_state.get_mut();
+        let mut tile_map = self.tile_map.get_mut();
+
+        client_state.pending_edits.retain(|edit| {
+            match edit.call.poll() {
+                // Still waiting on the server
+                None => return true,
+                // The server told us the actual state of every tile it refused to edit.
+                Some(Ok(reply)) => {
+                    // Only the tiles which were actually edited affected the stack.
+                    edit.revert_items(reply.rejected.len());
+
+                    for tile in reply.rejected {
+                        // (a bogus material is ignored; we're reconciling, not replicating)
+                        let Some(material) = tile_map.materials.get().try_get(tile.material) else {
+                            continue;
+                        };
+
+                        tile_map.set(edit.layer, IVec2::new(tile.x, tile.y), material);
+                    }
+                }
+                // We have no idea whether the edit went through so we assume that it didn't.
+                Some(Err(_)) => {
+                    edit.revert_items(edit.previous.len());
+
+                    for &(pos, material) in &edit.previous {
+                        tile_map.set(edit.layer, pos, material);
+                    }
+                }
+            }
+
+            false
+        });
+    }
+
     pub fn selected_tiles<B>(
         &self,
         config: TileLayerConfig,
@@ -219,10 +507,66 @@ impl ClientPlayerDriver {
     }
 }
 
+#[derive(Debug)]
+pub struct RemotePlayerDriver {
+    interpolator: Obj<SnapshotInterpolator>,
+    clock: Obj<ClientClock>,
+}
+
+impl RemotePlayerDriver {
+    pub fn new() -> impl CyclicCtor<Self> {
+        |me, _| {
+            let node = me.obj::<ClientRpcNode>();
+
+            node.builder().sub(PlayerRpcs::Motion).bind_message(
+                RpcAuthority::ServerOnly,
+                move |(), _target, motion: PlayerMotion| {
+                    me.get::<RemotePlayerDriver>().apply_motion(&motion);
+                    Ok(())
+                },
+            );
+
+            let interpolator = me.obj::<SnapshotInterpolator>();
+
+            if let Ok(motion) = node
+                .builder()
+                .sub(PlayerRpcs::Motion)
+                .read_catchup::<PlayerMotion>()
+            {
+                interpolator.get_mut().snap_to(motion_snapshot(&motion));
+            }
+
+            Self {
+                interpolator,
+                clock: me.deep_obj(),
+            }
+        }
+    }
+
+    pub fn update(&self) {
+        // Snapshots are stamped with server time so we can't place the player until we know it.
+        if let Some(now) = self.clock.get().server_time() {
+            self.interpolator.get_mut().update(now);
+        }
+    }
+
+    pub fn apply_motion(&self, motion: &PlayerMotion) {
+        self.interpolator.get_mut().push(motion_snapshot(motion));
+    }
+}
+
+fn motion_snapshot(motion: &PlayerMotion) -> PoseSnapshot {
+    PoseSnapshot {
+        time: motion.time,
+        pos: motion.pos.into(),
+        velocity: motion.velocity.into(),
+    }
+}
+
 impl ClientPlayerDriverObj {
     pub fn updater(&self) -> UpdateHandler {
         let me = self.obj.clone();
-        UpdateHandler::new(move || me.get().update())
+        UpdateHandler::new(move |dt| me.get().update(dt))
     }
 
     pub fn renderer(&self) -> RenderHandler {
@@ -262,3 +606,37 @@ pub fn create_player(
             })
         })
 }
+
+pub fn create_remote_player(
+    actors: &ActorManager,
+    rpc_id: RpcNodeId,
+    parent: Option<Obj<Transform>>,
+) -> Entity {
+    actors
+        .spawn()
+        .with_debug_label("remote player")
+        // Remote players have no collider on purpose. They are drawn where they were an
+        // interpolation delay ago so colliding with them would make our predicted movement diverge
+        // from the server's, which collides with where they are now.
+        .with_cyclic(Transform::new(parent))
+        .with_cyclic(ClientRpcNode::new(rpc_id))
+        .with_cyclic(SnapshotInterpolator::new(InterpolationConfig::default()))
+        .with_cyclic(RemotePlayerDriver::new())
+        // Handlers
+        .with_cyclic(|me, _| {
+            UpdateHandler::new(move |_dt| {
+                me.get::<RemotePlayerDriver>().update();
+            })
+        })
+        .with_cyclic(|me, _| {
+            RenderHandler::new(move || {
+                let pos = me.get::<Transform>().global_pos();
+                draw_circle(pos.x, pos.y, 0.3, ORANGE);
+            })
+        })
+        .with_cyclic(|me, _| {
+            DespawnHandler::new(move || {
+                me.get::<ClientRpcNode>().despawn();
+            })
+        })
+}
@@ -1,54 +1,85 @@
+use std::time::Duration;
+
 use aunty::{autoken::ImmutableBorrow, make_extensible, CyclicCtor, Entity, Obj, StrongEntity};
 use bytes::Bytes;
 use giaw_shared::{
-    game::actors::{
-        inventory::{InventoryData, ItemRegistry},
-        player::PlayerState,
+    game::{
+        actors::{
+            inventory::{InventoryData, ItemRegistry},
+            player::PlayerState,
+        },
+        scene::{create_game_scene, populate_game_scene},
+        services::replication::rpc_schema_fingerprint,
     },
     util::{
         game::{
             actors::{ActorManager, DespawnHandler, UpdateHandler},
-            kinematic::{KinematicManager, TileColliderDescriptor},
-            rpc::{decode_packet, encode_packet, ClientRpcManager, RpcNodeId, RpcPacket},
-            tile::{TileLayerConfig, TileMap},
-            transform::{ColliderManager, EntityExt, Transform},
+            rpc::{
+                decode_packet, encode_packet, ClientRpcManager, ClientRpcNode, RpcDelivery,
+                RpcNodeId, RpcPacket,
+            },
+            transform::Transform,
+        },
+        math::aabb::Aabb,
+        net::{
+            handshake::{HandshakeHello, HandshakeResponse},
+            transport::{DynClientTransport, QuadClientEvent},
         },
-        math::aabb::{Aabb, AabbI},
     },
 };
 use macroquad::{
     color::{BLACK, GRAY, GREEN, RED, WHITE},
-    math::{IVec2, Vec2},
+    math::Vec2,
     shapes::draw_rectangle,
+    text::draw_text,
 };
-use quad_net::quad_socket::client::QuadSocket;
 
-use crate::{engine::scene::RenderHandler, game::actors::inventory::InteractMode};
+use crate::{engine::scene::RenderHandler, net::policy::judge_rpc_errors};
 
 use super::{
     actors::{
-        inventory::{ClientItemDescriptor, ClientItemUseHandler},
-        player::{create_player, ClientPlayerDriver},
+        inventory::ClientItemDescriptor,
+        player::{create_player, create_remote_player},
     },
     services::{
         camera::CameraManager,
+        clock::ClientClock,
         render::{TileVisualDescriptor, WorldRenderer},
+        replication::{NodeFactory, NodeFactoryRegistry, TileReplicator},
     },
 };
 
+// How long messages for nodes which the server hasn't spawned for us yet are kept around.
+const RPC_DEFERRAL_WINDOW: Duration = Duration::from_secs(2);
+
 // === Components === //
 
 #[derive(Debug, Default)]
 pub struct GameClientState {
+    handshake_accepted: bool,
+    disconnect_reason: Option<String>,
     local_player: Option<Entity>,
 }
 
+impl GameClientState {
+    pub fn disconnect_reason(&self) -> Option<&str> {
+        self.disconnect_reason.as_deref()
+    }
+
+    fn set_disconnect_reason(&mut self, reason: String) {
+        // Only the first reason is interesting. For example, a handshake rejection is always
+        // followed by a kick.
+        self.disconnect_reason.get_or_insert(reason);
+    }
+}
+
 pub struct GameClientDriver {
     // Networking
-    socket: Obj<QuadSocket>,
+    socket: Obj<DynClientTransport>,
     rpc_manager: Obj<ClientRpcManager>,
 
     // Game
+    clock: Obj<ClientClock>,
     actors: Obj<ActorManager>,
     state: Obj<GameClientState>,
     renderer: Obj<WorldRenderer>,
@@ -61,26 +92,72 @@ impl GameClientDriver {
         move |me, _| Self {
             socket: me.obj(),
             rpc_manager: me.obj(),
+            clock: me.obj(),
             actors: me.obj(),
             state: me.obj(),
             renderer: me.obj(),
         }
     }
 
-    pub fn update(&self) {
+    pub fn update(&self, dt: f32) {
         // Process inbound packets
         {
-            let packet = self.socket.get_mut().try_recv();
-            if let Some(packet) = packet {
-                let packet = decode_packet::<RpcPacket>(&Bytes::from(packet)).unwrap();
-
-                let errors = self.rpc_manager.process_packet((), &packet);
-                if !errors.is_empty() {
-                    panic!("Errors while processing packet {packet:?}: {errors:#?}");
+            let events = self.socket.get_mut().poll();
+            for event in events {
+                match event {
+                    QuadClientEvent::Data(_) | QuadClientEvent::UnreliableData(_)
+                        if self.state.get().disconnect_reason.is_some() =>
+                    {
+                        // (ignore packets from a server which has already rejected us)
+                    }
+                    QuadClientEvent::UnreliableData(_) if !self.state.get().handshake_accepted => {
+                        // (unreliable packets can overtake the handshake response; we drop them)
+                    }
+                    QuadClientEvent::UnreliableData(packet) => self.process_rpc_packet(&packet),
+                    QuadClientEvent::Data(packet) => {
+                        // The first packet sent by the server is always its handshake response.
+                        if !self.state.get().handshake_accepted {
+                            match decode_packet::<HandshakeResponse>(&packet) {
+                                Ok(HandshakeResponse::Accepted) => {
+                                    self.state.get_mut().handshake_accepted = true;
+                                }
+                                Ok(HandshakeResponse::Rejected { reason }) => {
+                                    self.state.get_mut().set_disconnect_reason(format!(
+                                        "Server rejected our connection: {reason}"
+                                    ));
+                                }
+                                Err(_) => {
+                                    self.state.get_mut().set_disconnect_reason(
+                                        "The server speaks an incompatible protocol".to_string(),
+                                    );
+                                }
+                            }
+                            continue;
+                        }
+
+                        self.process_rpc_packet(&packet);
+                    }
+                    QuadClientEvent::Kicked(reason) => {
+                        self.state
+                            .get_mut()
+                            .set_disconnect_reason(format!("Kicked by the server: {reason}"));
+                    }
+                    QuadClientEvent::Disconnect(err) => {
+                        let reason = match err {
+                            Some(err) => format!("Lost connection to the server: {err}"),
+                            None => "The server closed the connection".to_string(),
+                        };
+                        self.state.get_mut().set_disconnect_reason(reason);
+                    }
                 }
             }
         }
 
+        // Keep our estimate of the server's clock up to date
+        if self.state.get().handshake_accepted {
+            self.clock.get_mut().update();
+        }
+
         // Update actors
         {
             let actor_mgr = self.actors.get();
@@ -88,24 +165,47 @@ impl GameClientDriver {
             cbit::cbit!(for actor in actor_mgr.iter_actors() {
                 let loaner = ImmutableBorrow::new();
                 if let Some(handler) = actor.try_get::<UpdateHandler>(&loaner) {
-                    handler.call();
+                    handler.call(dt);
                 };
             });
 
             actor_mgr.process_despawns();
         }
 
+        // Fail calls which the server never replied to
+        self.rpc_manager.get_mut().process_timeouts();
+
         // Process outbound packets
-        {
+        if self.state.get().handshake_accepted && self.state.get().disconnect_reason.is_none() {
+            self.rpc_manager.run_flushes();
+
             let mut socket = self.socket.get_mut();
             let mut manager = self.rpc_manager.get_mut();
 
-            for ((), packet) in manager.drain_queues() {
-                socket.send(&encode_packet(&packet));
+            for ((), delivery, packet) in manager.drain_queues() {
+                let packet = encode_packet(&packet);
+
+                match delivery {
+                    RpcDelivery::Reliable => socket.send(&packet),
+                    RpcDelivery::Unreliable => socket.send_unreliable(&packet),
+                }
             }
         }
     }
 
+    fn process_rpc_packet(&self, packet: &Bytes) {
+        let reason = match decode_packet::<RpcPacket>(packet) {
+            Ok(packet) => judge_rpc_errors(&self.rpc_manager.process_packet((), &packet)),
+            Err(err) => Some(format!("malformed packet: {err}")),
+        };
+
+        if let Some(reason) = reason {
+            self.state
+                .get_mut()
+                .set_disconnect_reason(format!("The server sent an invalid packet: {reason}"));
+        }
+    }
+
     pub fn render(&self) {
         // Render world
         self.renderer.get().render();
@@ -140,13 +240,17 @@ impl GameClientDriver {
                 );
             }
         }
+
+        if let Some(reason) = self.state.get().disconnect_reason() {
+            draw_text(reason, 10., 100., 30., RED);
+        }
     }
 }
 
 impl GameClientDriverObj {
     pub fn updater(&self) -> UpdateHandler {
         let me = self.obj.clone();
-        UpdateHandler::new(move || me.get().update())
+        UpdateHandler::new(move |dt| me.get().update(dt))
     }
 
     pub fn renderer(&self) -> RenderHandler {
@@ -157,22 +261,18 @@ impl GameClientDriverObj {
 
 // === Prefabs === //
 
-pub fn create_game(parent: Option<Obj<Transform>>) -> StrongEntity {
-    let scene = StrongEntity::new()
-        .with_debug_label("game scene root")
-        // Attach core services
-        .with_cyclic(Transform::new(parent))
-        .with(ActorManager::default())
-        .with(ColliderManager::default())
+pub fn create_game(parent: Option<Obj<Transform>>, transport: DynClientTransport) -> StrongEntity {
+    let scene = create_game_scene(parent)
+        // Attach client services
         .with(CameraManager::default())
-        .with(TileMap::default())
-        .with_cyclic(KinematicManager::new())
         .with_cyclic(WorldRenderer::new())
-        // Attach game services
-        .with(ItemRegistry::default())
         // Attach networking services
-        .with(ClientRpcManager::default())
-        .with(QuadSocket::connect("127.0.0.1:8080").unwrap())
+        .with(ClientRpcManager::default().with_deferral_window(RPC_DEFERRAL_WINDOW))
+        .with(transport)
+        .with_cyclic(ClientRpcNode::new(RpcNodeId::ROOT))
+        .with_cyclic(ClientClock::new())
+        .with_cyclic(NodeFactoryRegistry::new())
+        .with_cyclic(TileReplicator::new())
         // Attach scene entrypoints
         .with(GameClientState::default())
         .with_cyclic(GameClientDriver::new())
@@ -184,88 +284,56 @@ pub fn create_game(parent: Option<Obj<Transform>>) -> StrongEntity {
             })
         });
 
+    // Begin handshake
+    scene
+        .get_mut::<DynClientTransport>()
+        .send(&encode_packet(&HandshakeHello::new(
+            rpc_schema_fingerprint(),
+        )));
+
     // Setup initial scene
     {
-        // Setup basic map
-        {
-            let mut map = scene.get_mut::<TileMap>();
-            let layer = map.push_layer("under_player", TileLayerConfig::from_size(0.5));
-            let placeholder;
-
-            {
-                let mut materials = map.materials.get_mut();
-                materials.register("air", StrongEntity::new().with("air descriptor"));
-                placeholder = materials.register(
-                    "placeholder",
-                    StrongEntity::new()
-                        .with("placeholder descriptor")
-                        .with(TileVisualDescriptor { color: GREEN })
-                        .with(TileColliderDescriptor::new([Aabb::ZERO_TO_ONE])),
-                );
-            }
+        // Setup map and items
+        populate_game_scene(
+            scene.entity(),
+            |name, descriptor| match name {
+                "placeholder" => descriptor.with(TileVisualDescriptor { color: GREEN }),
+                _ => descriptor,
+            },
+            |name, descriptor| match name {
+                "stone" => descriptor.with(ClientItemDescriptor { color: GRAY }),
+                "blaster" => descriptor.with(ClientItemDescriptor { color: RED }),
+                _ => descriptor,
+            },
+        );
+
+        // Setup node factories
+        scene.get_mut::<NodeFactoryRegistry>().register(
+            "player",
+            NodeFactory::new(|scene, id, owned| {
+                let actors = scene.get::<ActorManager>();
+
+                if !owned {
+                    return create_remote_player(&actors, id, Some(scene.obj()));
+                }
 
-            for pos in AabbI::new_sized(IVec2::new(-10, 5), IVec2::new(20, 20))
-                .inclusive()
-                .iter()
-            {
-                map.set(layer, pos, placeholder);
-            }
-        }
+                let item_registry = scene.get::<ItemRegistry>();
+                let stone = item_registry.get("stone");
+                let blaster = item_registry.get("blaster");
 
-        // Setup player
-        {
-            let actors = scene.get::<ActorManager>();
-            let mut item_registry = scene.get_mut::<ItemRegistry>();
-
-            let stone = item_registry.register(
-                "stone",
-                StrongEntity::new()
-                    .with_debug_label("stone")
-                    .with(ClientItemDescriptor { color: GRAY })
-                    .with(ClientItemUseHandler::new(
-                        |player, _stack, mode, from, to| {
-                            let mut tile_map = player.deep_obj::<TileMap>().get_mut();
-                            let layer = tile_map.layer("under_player");
-                            let layer_config = tile_map.layer_config(layer);
-                            let material = match mode {
-                                InteractMode::Build => {
-                                    tile_map.materials.get().get_by_name("placeholder")
-                                }
-                                InteractMode::Break => tile_map.materials.get().get_by_name("air"),
-                            };
-
-                            let player = player.get::<ClientPlayerDriver>();
-                            cbit::cbit!(for pos in player.selected_tiles(layer_config, from, to) {
-                                tile_map.set(layer, pos, material);
-                            });
-                        },
-                    )),
-            );
-
-            let blaster = item_registry.register(
-                "blaster",
-                StrongEntity::new()
-                    .with_debug_label("blaster")
-                    .with(ClientItemDescriptor { color: RED })
-                    .with(ClientItemUseHandler::new(
-                        |player, _stack, _mode, _from, to| {
-                            player.get_mut::<PlayerState>().velocity =
-                                (to - player.get::<Transform>().global_pos()) * 10.;
-                        },
-                    )),
-            );
-
-            let player = create_player(&actors, RpcNodeId::ROOT, Some(scene.obj()));
-            player
-                .get_mut::<InventoryData>()
-                .insert_stack(&actors, stone, 1);
-
-            player
-                .get_mut::<InventoryData>()
-                .insert_stack(&actors, blaster, 1);
-
-            scene.get_mut::<GameClientState>().local_player = Some(player);
-        }
+                let player = create_player(&actors, id, Some(scene.obj()));
+                player
+                    .get_mut::<InventoryData>()
+                    .insert_stack(&actors, stone, 1);
+
+                player
+                    .get_mut::<InventoryData>()
+                    .insert_stack(&actors, blaster, 1);
+
+                scene.get_mut::<GameClientState>().local_player = Some(player);
+                player
+            }),
+        );
     }
 
     scene
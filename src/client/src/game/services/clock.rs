@@ -0,0 +1,151 @@
+use std::{collections::VecDeque, time::Duration};
+
+use aunty::CyclicCtor;
+use giaw_shared::{
+    game::services::replication::{GameSceneClockSample, GameSceneRpcs},
+    util::game::rpc::{ClientRpcNode, ClientRpcNodeSender, RpcCall},
+};
+use macroquad::time::get_time;
+
+// How long we wait for the server to answer a sync request before trying again.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(2);
+
+// We sample rapidly until our window is full so that we converge quickly after connecting.
+const FAST_SYNC_INTERVAL: f64 = 0.1;
+const SYNC_INTERVAL: f64 = 2.;
+const SAMPLE_WINDOW: usize = 8;
+
+// Small offset changes are slewed in at this rate, in seconds per second, so that server time never
+// jumps. Larger changes are snapped to.
+const MAX_SLEW_RATE: f64 = 0.05;
+const SNAP_THRESHOLD: f64 = 0.25;
+
+// === ClientClock === //
+
+// Estimates the server's timeline from periodic samples. Times are in seconds, matching both
+// `ServerClock` and macroquad's `get_time`.
+#[derive(Debug)]
+pub struct ClientClock {
+    sender: ClientRpcNodeSender,
+    pending: Option<PendingSample>,
+    next_sample: f64,
+    samples: VecDeque<ClockSample>,
+    offset: Option<f64>,
+    last_update: f64,
+}
+
+#[derive(Debug)]
+struct PendingSample {
+    call: RpcCall<GameSceneClockSample>,
+    sent_at: f64,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct ClockSample {
+    offset: f64,
+    rtt: f64,
+}
+
+impl ClientClock {
+    pub fn new() -> impl CyclicCtor<Self> {
+        |me, _| Self {
+            sender: me
+                .obj::<ClientRpcNode>()
+                .builder()
+                .sub(GameSceneRpcs::SyncClock)
+                .sender(),
+            pending: None,
+            next_sample: 0.,
+            samples: VecDeque::new(),
+            offset: None,
+            last_update: get_time(),
+        }
+    }
+
+    pub fn update(&mut self) {
+        let now = get_time();
+
+        // Collect the sample we're waiting on
+        if let Some(pending) = &self.pending {
+            match pending.call.poll() {
+                None => {}
+                Some(Ok(reply)) => {
+                    // We assume that the request and the reply took equally long to arrive.
+                    let rtt = now - pending.sent_at;
+                    self.samples.push_back(ClockSample {
+                        offset: reply.server_time + rtt / 2. - now,
+                        rtt,
+                    });
+
+                    if self.samples.len() > SAMPLE_WINDOW {
+                        self.samples.pop_front();
+                    }
+
+                    self.pending = None;
+                }
+                Some(Err(_)) => self.pending = None,
+            }
+        }
+
+        // Request the next sample
+        if self.pending.is_none() && now >= self.next_sample {
+            self.pending = Some(PendingSample {
+                call: self.sender.call((), &(), SYNC_TIMEOUT),
+                sent_at: now,
+            });
+
+            self.next_sample = now
+                + if self.samples.len() < SAMPLE_WINDOW {
+                    FAST_SYNC_INTERVAL
+                } else {
+                    SYNC_INTERVAL
+                };
+        }
+
+        // Move our offset towards the best estimate
+        if let Some(target) = self.best_sample().map(|sample| sample.offset) {
+            let max_slew = MAX_SLEW_RATE * (now - self.last_update);
+
+            self.offset = Some(match self.offset {
+                Some(offset) if (target - offset).abs() < SNAP_THRESHOLD => {
+                    offset + (target - offset).clamp(-max_slew, max_slew)
+                }
+                _ => target,
+            });
+        }
+
+        self.last_update = now;
+    }
+
+    // The sample with the lowest round-trip time is the least affected by asymmetric delays so
+    // we trust it the most.
+    fn best_sample(&self) -> Option<&ClockSample> {
+        self.samples.iter().min_by(|a, b| a.rtt.total_cmp(&b.rtt))
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.offset.is_some()
+    }
+
+    // The estimated difference between the server's time and our `get_time`.
+    pub fn offset(&self) -> Option<f64> {
+        self.offset
+    }
+
+    // The average round-trip time over our sample window.
+    pub fn rtt(&self) -> Option<f64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        Some(self.samples.iter().map(|sample| sample.rtt).sum::<f64>() / self.samples.len() as f64)
+    }
+
+    pub fn server_time(&self) -> Option<f64> {
+        self.to_server_time(get_time())
+    }
+
+    pub fn to_server_time(&self, local_time: f64) -> Option<f64> {
+        self.offset.map(|offset| local_time + offset)
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+
+use aunty::{CyclicCtor, Obj};
+use giaw_shared::util::game::transform::Transform;
+use macroquad::math::Vec2;
+
+// === SnapshotInterpolator === //
+
+#[derive(Debug, Copy, Clone)]
+pub struct InterpolationConfig {
+    // How far behind the latest snapshot entities are rendered, in seconds. This should comfortably
+    // exceed the interval at which snapshots are sent so that there's usually a snapshot on either
+    // side of the render time.
+    pub delay: f64,
+
+    // How long we keep extrapolating from the latest snapshot once we've run out of snapshots, in
+    // seconds. Past this, the entity freezes in place until the next snapshot arrives.
+    pub max_extrapolation: f64,
+
+    // The maximum number of snapshots we buffer.
+    pub max_snapshots: usize,
+}
+
+impl Default for InterpolationConfig {
+    fn default() -> Self {
+        Self {
+            delay: 0.1,
+            max_extrapolation: 0.25,
+            max_snapshots: 32,
+        }
+    }
+}
+
+impl InterpolationConfig {
+    pub fn with_delay(mut self, delay: f64) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn with_max_extrapolation(mut self, max_extrapolation: f64) -> Self {
+        self.max_extrapolation = max_extrapolation;
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PoseSnapshot {
+    pub time: f64,
+    pub pos: Vec2,
+    pub velocity: Vec2,
+}
+
+// Smooths out the motion of a `ClientRpcNode` whose transform is driven by the server. Authoritative
+// poses are buffered as they arrive and the transform is placed `delay` seconds behind the present
+// so that it can be interpolated between the two snapshots surrounding that time.
+#[derive(Debug)]
+pub struct SnapshotInterpolator {
+    xform: Obj<Transform>,
+    config: InterpolationConfig,
+    snapshots: VecDeque<PoseSnapshot>,
+}
+
+impl SnapshotInterpolator {
+    pub fn new(config: InterpolationConfig) -> impl CyclicCtor<Self> {
+        move |me, _| Self {
+            xform: me.obj(),
+            config,
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    pub fn config(&self) -> InterpolationConfig {
+        self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut InterpolationConfig {
+        &mut self.config
+    }
+
+    // Records an authoritative pose. Snapshots older than the latest one are ignored since they
+    // were overtaken by newer information.
+    pub fn push(&mut self, snapshot: PoseSnapshot) {
+        if self
+            .snapshots
+            .back()
+            .is_some_and(|latest| latest.time > snapshot.time)
+        {
+            return;
+        }
+
+        self.snapshots.push_back(snapshot);
+
+        while self.snapshots.len() > self.config.max_snapshots.max(1) {
+            self.snapshots.pop_front();
+        }
+    }
+
+    // Forgets every buffered snapshot and moves the transform to `snapshot` immediately. This is
+    // useful for teleports, which shouldn't be interpolated.
+    pub fn snap_to(&mut self, snapshot: PoseSnapshot) {
+        self.snapshots.clear();
+        self.snapshots.push_back(snapshot);
+        self.xform.get().set_global_pos(snapshot.pos);
+    }
+
+    pub fn sample(&self, render_time: f64) -> Option<Vec2> {
+        let latest = self.snapshots.back()?;
+
+        // If we're past the latest snapshot, extrapolate along its velocity for a little while.
+        if render_time >= latest.time {
+            let ahead = (render_time - latest.time).min(self.config.max_extrapolation);
+            return Some(latest.pos + latest.velocity * ahead as f32);
+        }
+
+        // Otherwise, find the pair of snapshots surrounding the render time.
+        let next_idx = self
+            .snapshots
+            .iter()
+            .position(|snapshot| snapshot.time > render_time)
+            .unwrap();
+
+        let Some(prev_idx) = next_idx.checked_sub(1) else {
+            // We don't have anything that old so the best we can do is the oldest snapshot.
+            return Some(self.snapshots[0].pos);
+        };
+
+        let prev = &self.snapshots[prev_idx];
+        let next = &self.snapshots[next_idx];
+        let span = next.time - prev.time;
+        let t = if span > 0. {
+            ((render_time - prev.time) / span) as f32
+        } else {
+            1.
+        };
+
+        Some(prev.pos.lerp(next.pos, t))
+    }
+
+    pub fn update(&mut self, now: f64) {
+        let render_time = now - self.config.delay;
+
+        // Drop the snapshots we've moved past, keeping the one right before the render time around
+        // so that we can still interpolate from it.
+        while self
+            .snapshots
+            .get(1)
+            .is_some_and(|snapshot| snapshot.time <= render_time)
+        {
+            self.snapshots.pop_front();
+        }
+
+        if let Some(pos) = self.sample(render_time) {
+            self.xform.get().set_global_pos(pos);
+        }
+    }
+}
@@ -1,2 +1,5 @@
 pub mod camera;
+pub mod clock;
+pub mod interpolation;
 pub mod render;
+pub mod replication;
@@ -0,0 +1,172 @@
+use aunty::{delegate, CyclicCtor, Entity};
+use giaw_shared::{
+    game::services::replication::{
+        GameSceneDespawnNode, GameSceneForgetNode, GameSceneRpcs, GameSceneSetTile,
+        GameSceneSpawnNode, GameSceneTileChunk,
+    },
+    util::game::{
+        actors::ActorManager,
+        rpc::{ClientRpcNode, RpcAuthority, RpcNodeId},
+        tile::{LayerIndex, TileMap, CHUNK_AREA},
+        transform::Transform,
+    },
+};
+use macroquad::math::IVec2;
+use rustc_hash::FxHashMap;
+
+// === Handlers === //
+
+delegate! {
+    pub fn NodeFactory(scene: Entity, id: RpcNodeId, owned: bool) -> Entity
+}
+
+// === NodeFactoryRegistry === //
+
+#[derive(Debug, Default)]
+pub struct NodeFactoryRegistry {
+    factories: FxHashMap<String, NodeFactory>,
+    spawned: FxHashMap<RpcNodeId, Entity>,
+}
+
+impl NodeFactoryRegistry {
+    pub fn new() -> impl CyclicCtor<Self> {
+        |me, _| {
+            let node = me.obj::<ClientRpcNode>();
+
+            node.builder().sub(GameSceneRpcs::SpawnNode).bind_message(
+                RpcAuthority::ServerOnly,
+                move |(), _, msg: GameSceneSpawnNode| {
+                    let factory = {
+                        let registry = me.get::<NodeFactoryRegistry>();
+
+                        if registry.spawned.contains_key(&msg.id) {
+                            anyhow::bail!("node with id {:?} was spawned twice", msg.id);
+                        }
+
+                        registry.factories.get(&msg.kind).cloned().ok_or_else(|| {
+                            anyhow::anyhow!("no factory registered for node kind {:?}", msg.kind)
+                        })?
+                    };
+
+                    let entity = factory.call(me, msg.id, msg.owned);
+                    me.get_mut::<NodeFactoryRegistry>()
+                        .spawned
+                        .insert(msg.id, entity);
+
+                    Ok(())
+                },
+            );
+
+            node.builder().sub(GameSceneRpcs::DespawnNode).bind_message(
+                RpcAuthority::ServerOnly,
+                move |(), _, msg: GameSceneDespawnNode| remove_node(me, msg.id),
+            );
+
+            // Nodes which left our area of interest are removed just like despawned ones.
+            node.builder().sub(GameSceneRpcs::ForgetNode).bind_message(
+                RpcAuthority::ServerOnly,
+                move |(), _, msg: GameSceneForgetNode| remove_node(me, msg.id),
+            );
+
+            Self::default()
+        }
+    }
+
+    pub fn register(&mut self, kind: impl Into<String>, factory: NodeFactory) {
+        let kind = kind.into();
+        debug_assert!(!self.factories.contains_key(&kind));
+        self.factories.insert(kind, factory);
+    }
+
+    pub fn spawned(&self, id: RpcNodeId) -> Option<Entity> {
+        self.spawned.get(&id).copied()
+    }
+}
+
+fn remove_node(scene: Entity, id: RpcNodeId) -> anyhow::Result<()> {
+    let entity = scene
+        .get_mut::<NodeFactoryRegistry>()
+        .spawned
+        .remove(&id)
+        .ok_or_else(|| anyhow::anyhow!("attempted to remove unknown node {id:?}"))?;
+
+    scene
+        .get::<ActorManager>()
+        .queue_despawn(&entity.get::<Transform>());
+
+    Ok(())
+}
+
+// === TileReplicator === //
+
+#[derive(Debug, Default)]
+pub struct TileReplicator;
+
+impl TileReplicator {
+    pub fn new() -> impl CyclicCtor<Self> {
+        |me, _| {
+            let node = me.obj::<ClientRpcNode>();
+
+            node.builder().sub(GameSceneRpcs::LoadChunk).bind_message(
+                RpcAuthority::ServerOnly,
+                move |(), _, chunk: GameSceneTileChunk| {
+                    let mut tile_map = me.get_mut::<TileMap>();
+                    let layer = check_layer(&tile_map, chunk.layer)?;
+
+                    let data = <&[u16; CHUNK_AREA as usize]>::try_from(chunk.data.as_slice())
+                        .map_err(|_| {
+                            anyhow::anyhow!(
+                                "chunk has {} tile(s), not {CHUNK_AREA}",
+                                chunk.data.len()
+                            )
+                        })?;
+
+                    for &material in data {
+                        check_material(&tile_map, material)?;
+                    }
+
+                    tile_map.layers[layer.0]
+                        .data
+                        .set_chunk(IVec2::new(chunk.x, chunk.y), data);
+
+                    Ok(())
+                },
+            );
+
+            node.builder().sub(GameSceneRpcs::SetTile).bind_message(
+                RpcAuthority::ServerOnly,
+                move |(), _, edits: Vec<GameSceneSetTile>| {
+                    let mut tile_map = me.get_mut::<TileMap>();
+
+                    for edit in edits {
+                        let layer = check_layer(&tile_map, edit.layer)?;
+                        check_material(&tile_map, edit.material)?;
+
+                        let material = tile_map.materials.get().get(edit.material);
+                        tile_map.set(layer, IVec2::new(edit.x, edit.y), material);
+                    }
+
+                    Ok(())
+                },
+            );
+
+            Self
+        }
+    }
+}
+
+fn check_layer(tile_map: &TileMap, layer: u32) -> anyhow::Result<LayerIndex> {
+    if (layer as usize) < tile_map.layers.len() {
+        Ok(LayerIndex(layer as usize))
+    } else {
+        anyhow::bail!("unknown tile layer {layer}")
+    }
+}
+
+fn check_material(tile_map: &TileMap, material: u16) -> anyhow::Result<()> {
+    if tile_map.materials.get().try_get(material).is_none() {
+        anyhow::bail!("unknown material {material}");
+    }
+
+    Ok(())
+}
@@ -1,10 +1,15 @@
 use std::{future::Future, pin::pin};
 
-use giaw_client::{engine::scene::RenderHandler, game::entry::create_game};
+use giaw_client::{
+    engine::scene::RenderHandler,
+    game::entry::create_game,
+    net::transport::{QuadClient, QuadClientConfig},
+};
 use giaw_shared::util::game::actors::{DespawnHandler, UpdateHandler};
 use macroquad::{
     input::{is_key_pressed, is_quit_requested},
     miniquad::KeyCode,
+    time::get_frame_time,
     window::next_frame,
 };
 
@@ -24,14 +29,15 @@ fn main() {
 }
 
 async fn amain() {
-    let scene = create_game(None);
+    let socket = QuadClient::connect("127.0.0.1:8080", QuadClientConfig::default()).unwrap();
+    let scene = create_game(None, Box::new(socket));
 
     while !is_quit_requested() {
         if is_key_pressed(KeyCode::Escape) {
             break;
         }
 
-        scene.get::<UpdateHandler>().call();
+        scene.get::<UpdateHandler>().call(get_frame_time());
         scene.get::<RenderHandler>().call();
         next_frame().await;
     }
@@ -0,0 +1,25 @@
+use giaw_shared::util::game::rpc::RpcError;
+
+// Returns the reason for which we should disconnect from the server, if any.
+pub fn judge_rpc_errors(errors: &[RpcError]) -> Option<String> {
+    for error in errors {
+        match error {
+            // These can be caused by the server racing against our own despawns or timeouts or by
+            // the server mentioning nodes outside of our area of interest.
+            RpcError::UnknownNode { .. }
+            | RpcError::UnknownPath { .. }
+            | RpcError::UnknownReference { .. }
+            | RpcError::UnknownCall { .. } => {}
+
+            // Everything else means that we have diverged from the server's state so there is no
+            // point in continuing.
+            RpcError::NullNodeId
+            | RpcError::UnexpectedCatchup
+            | RpcError::Decode { .. }
+            | RpcError::Handler { .. }
+            | RpcError::Unauthorized { .. } => return Some(error.to_string()),
+        }
+    }
+
+    None
+}
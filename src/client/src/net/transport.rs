@@ -0,0 +1,313 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::{Bytes, BytesMut};
+use giaw_shared::util::net::{
+    datagram::{Datagram, MAX_DATAGRAM_SIZE},
+    framing::{Frame, FrameCodec, DEFAULT_MAX_FRAME_SIZE},
+    transport::ClientTransport,
+};
+
+pub use giaw_shared::util::net::transport::QuadClientEvent;
+
+// === Client === //
+
+const READ_CHUNK_SIZE: usize = 4096;
+
+#[derive(Debug, Clone)]
+pub struct QuadClientConfig {
+    pub max_frame_size: usize,
+    pub idle_timeout: Duration,
+
+    // Whether we should send and receive unreliable data as datagrams if the server supports it.
+    pub datagrams: bool,
+
+    // How often we remind the server of our datagram address. This also keeps NAT mappings alive.
+    pub datagram_hello_interval: Duration,
+}
+
+impl Default for QuadClientConfig {
+    fn default() -> Self {
+        Self {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            idle_timeout: Duration::from_secs(10),
+            datagrams: true,
+            datagram_hello_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct QuadClient {
+    // The reader thread also writes to the stream to answer pings so writes must be synchronized
+    // to avoid interleaving frames.
+    writer: Arc<Mutex<TcpStream>>,
+    codec: FrameCodec,
+    event_send: Sender<QuadClientEvent>,
+    event_recv: Receiver<QuadClientEvent>,
+    disconnected: bool,
+    datagrams: Option<Arc<UdpSocket>>,
+
+    // The token with which we label our datagrams. This is `None` until the server tells us about
+    // it, which it only does if it accepts datagrams.
+    token: Arc<Mutex<Option<u64>>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl QuadClient {
+    pub fn connect(addr: impl ToSocketAddrs, config: QuadClientConfig) -> anyhow::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+
+        // The server sends heartbeats regularly so going this long without receiving anything
+        // means that the connection is dead.
+        stream.set_read_timeout(Some(config.idle_timeout))?;
+
+        let codec = FrameCodec::new(config.max_frame_size);
+        let (event_send, event_recv) = channel();
+
+        // Spin up a thread to process inbound packets
+        let mut reader = stream.try_clone()?;
+        let writer = Arc::new(Mutex::new(stream));
+        let reader_writer = writer.clone();
+        let reader_send = event_send.clone();
+        let idle_timeout = config.idle_timeout;
+        let token = Arc::new(Mutex::new(None));
+        let reader_token = token.clone();
+
+        thread::spawn(move || {
+            let mut buffer = BytesMut::new();
+            let mut chunk = [0u8; READ_CHUNK_SIZE];
+
+            let err = loop {
+                // Handle every frame which has been fully received...
+                match codec.decode(&mut buffer) {
+                    Ok(Some(Frame::Data(data))) => {
+                        if reader_send.send(QuadClientEvent::Data(data)).is_err() {
+                            // The `QuadClient` was dropped.
+                            return;
+                        }
+                        continue;
+                    }
+                    Ok(Some(Frame::Ping(nonce))) => {
+                        if let Err(err) = write_frame(&reader_writer, codec, &Frame::Pong(nonce)) {
+                            break Some(err);
+                        }
+                        continue;
+                    }
+                    Ok(Some(Frame::Pong(_))) => {
+                        // (we never send pings so we just ignore these)
+                        continue;
+                    }
+                    Ok(Some(Frame::DatagramToken(token))) => {
+                        *reader_token.lock().unwrap() = Some(token);
+                        continue;
+                    }
+                    Ok(Some(Frame::Disconnect(reason))) => {
+                        let _ = reader.shutdown(Shutdown::Both);
+                        let _ = reader_send.send(QuadClientEvent::Kicked(reason));
+                        return;
+                    }
+                    Ok(None) => {}
+                    Err(err) => break Some(err),
+                }
+
+                // ...and then wait for more data.
+                match reader.read(&mut chunk) {
+                    // The socket closed naturally
+                    Ok(0) => break None,
+                    Ok(len) => buffer.extend_from_slice(&chunk[..len]),
+                    Err(err)
+                        if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                    {
+                        break Some(anyhow::anyhow!(
+                            "server timed out after not sending anything for {idle_timeout:?}"
+                        ));
+                    }
+                    Err(err) => break Some(anyhow::Error::new(err)),
+                }
+            };
+
+            let _ = reader.shutdown(Shutdown::Both);
+            let _ = reader_send.send(QuadClientEvent::Disconnect(err));
+        });
+
+        // Spin up a thread to process inbound datagrams
+        let closed = Arc::new(AtomicBool::new(false));
+        let datagrams = if config.datagrams {
+            let server_addr = writer.lock().unwrap().peer_addr()?;
+            let datagrams = Arc::new(bind_datagrams(server_addr)?);
+
+            spawn_datagram_thread(
+                datagrams.clone(),
+                token.clone(),
+                closed.clone(),
+                event_send.clone(),
+                config.datagram_hello_interval,
+            )?;
+
+            Some(datagrams)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            writer,
+            codec,
+            event_send,
+            event_recv,
+            disconnected: false,
+            datagrams,
+            token,
+            closed,
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        !self.disconnected
+    }
+}
+
+impl ClientTransport for QuadClient {
+    fn poll(&mut self) -> Vec<QuadClientEvent> {
+        let mut events = Vec::new();
+
+        // Only the first disconnect is reported. Subsequent events are just the reader thread
+        // noticing that we closed the socket on our end.
+        if self.disconnected {
+            return events;
+        }
+
+        for event in self.event_recv.try_iter() {
+            let is_disconnect = event.is_disconnect();
+            events.push(event);
+
+            if is_disconnect {
+                self.disconnected = true;
+                break;
+            }
+        }
+
+        events
+    }
+
+    fn send(&mut self, data: &[u8]) {
+        if self.disconnected {
+            return;
+        }
+
+        let frame = Frame::Data(Bytes::copy_from_slice(data));
+        if let Err(err) = write_frame(&self.writer, self.codec, &frame) {
+            let _ = self.writer.lock().unwrap().shutdown(Shutdown::Both);
+            let _ = self.event_send.send(QuadClientEvent::Disconnect(Some(err)));
+        }
+    }
+
+    fn send_unreliable(&mut self, data: &[u8]) {
+        if self.disconnected {
+            return;
+        }
+
+        let token = *self.token.lock().unwrap();
+        if let (Some(datagrams), Some(token)) = (&self.datagrams, token) {
+            if let Some(datagram) =
+                Datagram::Data(Bytes::copy_from_slice(data)).encode_client(token)
+            {
+                // (lost datagrams are expected so we don't care whether this succeeds)
+                let _ = datagrams.send(&datagram);
+                return;
+            }
+        }
+
+        // The server doesn't accept datagrams or the data is too large for one so we fall back to
+        // the stream.
+        self.send(data);
+    }
+}
+
+impl Drop for QuadClient {
+    fn drop(&mut self) {
+        let _ = self.writer.lock().unwrap().shutdown(Shutdown::Both);
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+fn bind_datagrams(server_addr: SocketAddr) -> anyhow::Result<UdpSocket> {
+    let local_addr = match server_addr {
+        SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+    };
+
+    // Connecting the socket makes the OS drop datagrams which weren't sent by the server.
+    let socket = UdpSocket::bind(local_addr)?;
+    socket.connect(server_addr)?;
+    Ok(socket)
+}
+
+fn spawn_datagram_thread(
+    datagrams: Arc<UdpSocket>,
+    token: Arc<Mutex<Option<u64>>>,
+    closed: Arc<AtomicBool>,
+    event_send: Sender<QuadClientEvent>,
+    hello_interval: Duration,
+) -> anyhow::Result<()> {
+    // We wake up at least this often to check whether we should send a hello or shut down.
+    datagrams.set_read_timeout(Some(hello_interval))?;
+
+    thread::spawn(move || {
+        let mut buffer = [0u8; MAX_DATAGRAM_SIZE + 1];
+        let mut last_hello = None::<Instant>;
+
+        while !closed.load(Ordering::Relaxed) {
+            // Remind the server of our address
+            let token = *token.lock().unwrap();
+            if let Some(token) = token {
+                if last_hello.map_or(true, |last| last.elapsed() >= hello_interval) {
+                    let _ = datagrams.send(&Datagram::Hello.encode_client(token).unwrap());
+                    last_hello = Some(Instant::now());
+                }
+            }
+
+            // Handle the next datagram. Errors are either timeouts or reports of datagrams which
+            // failed to reach the server, neither of which we care about.
+            let Ok(len) = datagrams.recv(&mut buffer) else {
+                continue;
+            };
+
+            // Malformed datagrams are dropped like lost ones.
+            if len > MAX_DATAGRAM_SIZE {
+                continue;
+            }
+
+            if let Ok(Datagram::Data(data)) =
+                Datagram::decode_server(Bytes::copy_from_slice(&buffer[..len]))
+            {
+                if event_send
+                    .send(QuadClientEvent::UnreliableData(data))
+                    .is_err()
+                {
+                    // The `QuadClient` was dropped.
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn write_frame(writer: &Mutex<TcpStream>, codec: FrameCodec, frame: &Frame) -> anyhow::Result<()> {
+    let mut buf = BytesMut::new();
+    codec.encode(frame, &mut buf)?;
+    writer.lock().unwrap().write_all(&buf)?;
+    Ok(())
+}
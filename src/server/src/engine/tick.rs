@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+#[derive(Debug)]
+pub struct TickScheduler {
+    tick_duration: Duration,
+    tick: u64,
+    tick_start: Instant,
+    deadline: Instant,
+}
+
+impl TickScheduler {
+    pub fn new(tick_rate: u32) -> Self {
+        assert!(tick_rate > 0, "tick rate must be non-zero");
+
+        let now = Instant::now();
+        let tick_duration = Duration::from_secs(1) / tick_rate;
+
+        Self {
+            tick_duration,
+            tick: 0,
+            tick_start: now,
+            deadline: now + tick_duration,
+        }
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn dt(&self) -> f32 {
+        self.tick_duration.as_secs_f32()
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn begin_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick_start = Instant::now();
+        self.tick
+    }
+
+    pub fn end_tick(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.tick_start;
+
+        if elapsed > self.tick_duration {
+            log::warn!(
+                "Tick {} took {elapsed:?}, which exceeds its budget of {:?}",
+                self.tick,
+                self.tick_duration,
+            );
+        }
+
+        self.deadline += self.tick_duration;
+
+        // If we've fallen behind by more than a tick, there's no use in trying to catch up by
+        // running several ticks back-to-back.
+        if self.deadline < now {
+            log::warn!(
+                "Server is running {:?} behind schedule; skipping ahead",
+                now - self.deadline,
+            );
+            self.deadline = now;
+        }
+    }
+}
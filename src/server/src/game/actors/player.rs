@@ -0,0 +1,267 @@
+use std::mem;
+
+use aunty::{autoken::ImmutableBorrow, CyclicCtor, Entity, Obj};
+use giaw_shared::{
+    game::{
+        actors::{
+            inventory::{BlockItemDescriptor, InteractMode, InventoryData},
+            player::{
+                PlayerInput, PlayerRpcs, PlayerState, PlayerUseItem, PlayerUseItemReply,
+                MAX_INPUT_DT, MAX_TILES_PER_USE, PLAYER_REACH,
+            },
+        },
+        services::replication::GameSceneSetTile,
+    },
+    util::game::{
+        actors::{ActorManager, DespawnHandler, UpdateHandler},
+        kinematic::{AnyCollision, KinematicManager},
+        rpc::{RpcAuthority, RpcChannel, RpcNodeId, ServerRpcNode, ServerRpcNodeSender},
+        tile::TileMap,
+        transform::{Collider, EntityExt, Transform},
+    },
+};
+use glam::{IVec2, Vec2};
+
+use crate::game::services::{
+    clock::ServerClock,
+    replication::{ReplicatedNode, TileReplicator},
+};
+
+// The most simulation time a client can bank up by sending inputs with short time steps. This gives
+// some leeway for jittery connections while preventing clients from moving faster than real-time.
+const MAX_INPUT_BUDGET: f32 = 0.5;
+
+// === Components === //
+
+#[derive(Debug)]
+pub struct ServerPlayerDriver {
+    // Component dependencies
+    xform: Obj<Transform>,
+    inventory: Obj<InventoryData>,
+    state: Obj<PlayerState>,
+    motion_sender: ServerRpcNodeSender,
+
+    // Deep dependencies
+    tile_map: Obj<TileMap>,
+    kinematic: Obj<KinematicManager>,
+    tile_replicator: Obj<TileReplicator>,
+    clock: Obj<ServerClock>,
+
+    // Movement state
+    last_input: Option<u32>,
+    input_budget: f32,
+    motion_dirty: bool,
+}
+
+impl ServerPlayerDriver {
+    pub fn new() -> impl CyclicCtor<Self> {
+        |me, _| {
+            let node = me.obj::<ServerRpcNode>();
+
+            node.builder().sub(PlayerRpcs::UseItem).bind_request(
+                RpcAuthority::OwnerOnly,
+                move |_peer, _target, req: PlayerUseItem| {
+                    me.get::<ServerPlayerDriver>().use_item(req)
+                },
+            );
+
+            node.builder().sub(PlayerRpcs::Input).bind_message(
+                RpcAuthority::OwnerOnly,
+                move |_peer, _target, input: PlayerInput| {
+                    me.get_mut::<ServerPlayerDriver>().apply_input(input)
+                },
+            );
+
+            node.builder()
+                .sub(PlayerRpcs::Motion)
+                .bind_catchup(move |_peer, _target| {
+                    let driver = me.get::<ServerPlayerDriver>();
+                    let time = driver.clock.get().server_time();
+                    me.get::<PlayerState>().motion(time, driver.last_input)
+                });
+
+            Self {
+                xform: me.obj(),
+                inventory: me.obj(),
+                state: me.obj(),
+                // Only the latest motion matters so it shouldn't wait on lost packets.
+                motion_sender: node
+                    .builder()
+                    .sub(PlayerRpcs::Motion)
+                    .on_channel(RpcChannel::UnreliableSequenced)
+                    .sender(),
+                tile_map: me.deep_obj(),
+                kinematic: me.deep_obj(),
+                tile_replicator: me.deep_obj(),
+                clock: me.deep_obj(),
+                last_input: None,
+                input_budget: MAX_INPUT_DT,
+                motion_dirty: false,
+            }
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.input_budget = (self.input_budget + dt).min(MAX_INPUT_BUDGET);
+
+        // Tell everyone where the player ended up. The owner uses `last_input` to figure out which
+        // of its predicted inputs still have to be replayed on top of this.
+        if mem::take(&mut self.motion_dirty) {
+            let time = self.clock.get().server_time();
+            self.motion_sender
+                .broadcast(&self.state.get().motion(time, self.last_input));
+        }
+    }
+
+    pub fn apply_input(&mut self, mut input: PlayerInput) -> anyhow::Result<()> {
+        // Stale inputs are ignored rather than rejected since they're harmless.
+        if self.last_input.is_some_and(|last| input.seq <= last) {
+            return Ok(());
+        }
+
+        // This must happen before clamping since `f32::min` would turn a NaN into a valid time step.
+        anyhow::ensure!(
+            input.dt.is_finite(),
+            "input time step {} is not finite",
+            input.dt
+        );
+
+        // Inputs beyond the client's time budget are still simulated, just with a shorter time
+        // step. The client will reconcile with the resulting motion.
+        input.dt = input.dt.min(self.input_budget.min(MAX_INPUT_DT));
+        self.state.get_mut().apply_input(&input)?;
+        self.input_budget -= input.dt.max(0.);
+
+        self.last_input = Some(input.seq);
+        self.motion_dirty = true;
+
+        Ok(())
+    }
+
+    pub fn use_item(&self, req: PlayerUseItem) -> anyhow::Result<PlayerUseItemReply> {
+        // Validate the request's shape. Failures here indicate a misbehaving client rather than a
+        // misprediction so we reject the entire request.
+        let (stack, block) = {
+            let inventory = self.inventory.get();
+            let Some(Some(stack)) = inventory.stacks().get(req.slot as usize) else {
+                anyhow::bail!("slot {} holds no item", req.slot);
+            };
+
+            let loaner = ImmutableBorrow::new();
+            let Some(block) = stack
+                .get()
+                .material
+                .try_get::<BlockItemDescriptor>(&loaner)
+                .map(|block| block.material)
+            else {
+                anyhow::bail!("the item in slot {} cannot edit tiles", req.slot);
+            };
+
+            (stack.clone(), block)
+        };
+
+        anyhow::ensure!(
+            req.tiles.len() <= MAX_TILES_PER_USE,
+            "attempted to edit {} tiles at once (the limit is {MAX_TILES_PER_USE})",
+            req.tiles.len(),
+        );
+
+        // Validate each edit individually
+        let (layer, air) = {
+            let tile_map = self.tile_map.get();
+            let air = tile_map.materials.get().get_by_name("air");
+            (tile_map.layer("under_player"), air)
+        };
+        let player_pos = self.xform.get().global_pos();
+        let mut rejected = Vec::new();
+
+        // Every tile built uses up one of the stack's items and every tile broken gives one back.
+        // Tiles beyond what the stack can pay for are rejected like any other misprediction.
+        let mut count = stack.get().count;
+
+        for (x, y) in req.tiles {
+            let pos = IVec2::new(x, y);
+            let (current, tile_aabb) = {
+                let mut tile_map = self.tile_map.get_mut();
+                (
+                    tile_map.get(layer, pos),
+                    tile_map.tile_to_actor_rect(layer, pos),
+                )
+            };
+
+            let accepted = tile_aabb.center().distance(player_pos) <= PLAYER_REACH
+                && match req.mode {
+                    InteractMode::Build => {
+                        // (shrunk so that we don't pick up actors which merely touch the tile)
+                        count > 0
+                            && current.id == air.id
+                            && !self.kinematic.get().has_colliders_in(
+                                tile_aabb.shrink(Vec2::splat(0.01)),
+                                |collision| matches!(collision, AnyCollision::Collider(..)),
+                            )
+                    }
+                    InteractMode::Break => current.id != air.id,
+                };
+
+            if accepted {
+                let material = match req.mode {
+                    InteractMode::Build => {
+                        count -= 1;
+                        block
+                    }
+                    InteractMode::Break => {
+                        count = count.saturating_add(1);
+                        air
+                    }
+                };
+                self.tile_replicator
+                    .get_mut()
+                    .set_tile(layer, pos, material);
+            } else {
+                rejected.push(GameSceneSetTile {
+                    layer: layer.0 as u32,
+                    x,
+                    y,
+                    material: current.id,
+                });
+            }
+        }
+
+        stack.get_mut().count = count;
+
+        Ok(PlayerUseItemReply { rejected })
+    }
+}
+
+// === Prefabs === //
+
+pub fn create_server_player(
+    actors: &ActorManager,
+    rpc_id: RpcNodeId,
+    parent: Option<Obj<Transform>>,
+    owner: Entity,
+) -> Entity {
+    actors
+        .spawn()
+        .with_debug_label("player")
+        .with_cyclic(Transform::new(parent))
+        .with_cyclic(Collider::new_centered(Vec2::ZERO, Vec2::splat(0.6)))
+        .with_cyclic(ServerRpcNode::new_owned(rpc_id, owner))
+        .with_cyclic(InventoryData::new(9 * 4))
+        .with_cyclic(PlayerState::new())
+        .with_cyclic(ServerPlayerDriver::new())
+        .with_cyclic(ReplicatedNode::new("player"))
+        // Handlers
+        .with_cyclic(|me, _| {
+            UpdateHandler::new(move |dt| {
+                me.get_mut::<ServerPlayerDriver>().update(dt);
+            })
+        })
+        .with_cyclic(|me, _| {
+            DespawnHandler::new(move || {
+                me.get::<Collider>().despawn();
+                me.get::<ServerRpcNode>().despawn();
+                me.get::<ReplicatedNode>().despawn();
+            })
+        })
+}
@@ -0,0 +1,233 @@
+use aunty::{autoken::ImmutableBorrow, Entity, Obj, StrongEntity};
+use bytes::Bytes;
+use giaw_shared::{
+    game::{
+        actors::inventory::{InventoryData, ItemRegistry},
+        scene::{create_game_scene, generate_game_map, populate_game_scene},
+        services::replication::rpc_schema_fingerprint,
+    },
+    util::{
+        game::{
+            actors::{ActorManager, UpdateHandler},
+            rpc::{
+                decode_packet, encode_packet, RpcDelivery, RpcNodeId, RpcPacket, ServerRpcManager,
+                ServerRpcNode,
+            },
+            transform::Transform,
+        },
+        net::{
+            handshake::{HandshakeHello, HandshakeResponse},
+            transport::{DynServerTransport, QuadPeerId, QuadServerEvent},
+        },
+    },
+};
+
+use crate::net::{
+    policy::PeerPolicy,
+    session::{SessionJoinHandler, SessionLeaveHandler, SessionManager, SessionState},
+};
+
+use super::{
+    actors::player::create_server_player,
+    services::{
+        clock::ServerClock,
+        interest::{InterestConfig, InterestManager},
+        replication::{NodeSpawner, TileReplicator},
+    },
+};
+
+pub const TICK_RATE: u32 = 30;
+
+// === Prefabs === //
+
+// Creates the server's scene, complete with its map. The scene is driven by calling its
+// `UpdateHandler` once per tick and `process_net_events` followed by `flush_net_queues` whenever
+// the transport has something for us.
+pub fn create_game(parent: Option<Obj<Transform>>, transport: DynServerTransport) -> StrongEntity {
+    let root = create_game_scene(parent)
+        // Attach networking services
+        .with(ServerRpcManager::default())
+        .with(SessionManager::new(HandshakeHello::new(
+            rpc_schema_fingerprint(),
+        )))
+        .with(PeerPolicy::default())
+        .with(transport)
+        .with_cyclic(ServerRpcNode::new(RpcNodeId::ROOT))
+        .with_cyclic(ServerClock::new())
+        .with_cyclic(NodeSpawner::new())
+        .with_cyclic(TileReplicator::new())
+        .with_cyclic(InterestManager::new(InterestConfig::default()))
+        // Attach scene entrypoints
+        .with_cyclic(|me, _| {
+            UpdateHandler::new(move |dt| {
+                let actor_mgr = me.get::<ActorManager>();
+
+                cbit::cbit!(for actor in actor_mgr.iter_actors() {
+                    let loaner = ImmutableBorrow::new();
+                    if let Some(handler) = actor.try_get::<UpdateHandler>(&loaner) {
+                        handler.call(dt);
+                    };
+                });
+
+                actor_mgr.process_despawns();
+                me.get_mut::<ServerRpcManager>().process_timeouts();
+                me.get_mut::<InterestManager>().update();
+
+                // Send out the changes made during this tick. Flushing here rather than after
+                // every network event coalesces a tick's worth of changes into a single message.
+                me.get_mut::<TileReplicator>().flush();
+                me.obj::<ServerRpcManager>().run_flushes();
+            })
+        })
+        .with_cyclic(|me, _| {
+            SessionJoinHandler::new(move |session| {
+                let actors = me.get::<ActorManager>();
+                let item_registry = me.get::<ItemRegistry>();
+
+                // Catch the peer up on the scene. The map and nodes around it will be sent at the
+                // end of the next tick.
+                me.obj::<ServerRpcNode>().queue_catchup(session);
+
+                // Spawn the session's player. It will be announced to this peer and everyone near
+                // it at the end of the next tick.
+                let rpc_id = me.get_mut::<ServerRpcManager>().allocate_id();
+                let player = create_server_player(&actors, rpc_id, Some(me.obj()), session);
+
+                player.get_mut::<InventoryData>().insert_stack(
+                    &actors,
+                    item_registry.get("stone"),
+                    1,
+                );
+
+                player.get_mut::<InventoryData>().insert_stack(
+                    &actors,
+                    item_registry.get("blaster"),
+                    1,
+                );
+
+                session.get_mut::<SessionState>().player = Some(player);
+            })
+        })
+        .with_cyclic(|me, _| {
+            SessionLeaveHandler::new(move |session| {
+                me.get_mut::<TileReplicator>().forget_peer(session);
+
+                let Some(player) = session.get_mut::<SessionState>().player.take() else {
+                    return;
+                };
+
+                me.get::<ActorManager>()
+                    .queue_despawn(&player.get::<Transform>());
+            })
+        });
+
+    // Setup initial scene
+    populate_game_scene(root.entity(), |_, material| material, |_, item| item);
+    generate_game_map(root.entity());
+
+    root
+}
+
+// === Networking === //
+
+pub fn process_net_events(root: Entity) {
+    // Poll for new network events
+    let events = root.get_mut::<DynServerTransport>().poll().unwrap();
+
+    // Handle the packets
+    for event in events {
+        match event {
+            QuadServerEvent::PeerConnected { id, addr } => {
+                log::info!("Socket {id:?} at address {addr:?} connected!");
+                root.get_mut::<SessionManager>().add_peer(id);
+            }
+            QuadServerEvent::PeerData { id, data } => {
+                // The first packet sent by a peer is always its handshake.
+                if root.get::<SessionManager>().is_handshaking(id) {
+                    let response = root
+                        .get_mut::<SessionManager>()
+                        .complete_handshake(id, &data);
+
+                    root.get_mut::<DynServerTransport>()
+                        .send(id, encode_packet(&response));
+
+                    match response {
+                        HandshakeResponse::Accepted => {
+                            let peer = root.get::<SessionManager>().peer_by_id(id);
+                            root.get::<SessionJoinHandler>().call(peer);
+                        }
+                        HandshakeResponse::Rejected { reason } => {
+                            log::warn!("Socket {id:?} failed its handshake: {reason}");
+                            root.get_mut::<DynServerTransport>().kick(id, &reason);
+                        }
+                    }
+
+                    continue;
+                }
+
+                process_rpc_packet(root, id, &data);
+            }
+            QuadServerEvent::PeerUnreliableData { id, data } => {
+                // Unreliable data can overtake the peer's handshake, in which case it's dropped.
+                if root.get::<SessionManager>().is_handshaking(id) {
+                    continue;
+                }
+
+                process_rpc_packet(root, id, &data);
+            }
+            QuadServerEvent::PeerDisconnect { id, err } => {
+                log::info!("Socket {id:?} disconnected (error: {err:?})!");
+                remove_peer(root, id);
+            }
+        }
+    }
+}
+
+fn process_rpc_packet(root: Entity, id: QuadPeerId, data: &Bytes) {
+    // Ignore packets still in flight from peers we've already disconnected.
+    let Some(peer) = root.get::<SessionManager>().try_peer_by_id(id) else {
+        return;
+    };
+
+    let kick_reason = match decode_packet::<RpcPacket>(data) {
+        Ok(data) => {
+            let errors = root.obj::<ServerRpcManager>().process_packet(peer, &data);
+
+            root.get::<PeerPolicy>()
+                .judge_rpc_errors(&mut peer.get_mut::<SessionState>(), &errors)
+        }
+        Err(err) => root.get::<PeerPolicy>().judge_malformed_packet(&err),
+    };
+
+    if let Some(reason) = kick_reason {
+        log::warn!("Kicking socket {id:?}: {reason}");
+        root.get_mut::<DynServerTransport>().kick(id, &reason);
+
+        // We remove the session immediately so that the packets it still has in flight are
+        // ignored.
+        remove_peer(root, id);
+    }
+}
+
+pub fn flush_net_queues(root: Entity) {
+    let mut server = root.get_mut::<DynServerTransport>();
+    for (peer, delivery, packet) in root.get_mut::<ServerRpcManager>().drain_queues() {
+        let id = peer.get::<SessionState>().id;
+        let packet = encode_packet(&packet);
+
+        match delivery {
+            RpcDelivery::Reliable => server.send(id, packet),
+            RpcDelivery::Unreliable => server.send_unreliable(id, packet),
+        }
+    }
+}
+
+fn remove_peer(root: Entity, id: QuadPeerId) {
+    let peer = root.get::<SessionManager>().try_peer_by_id(id);
+    if let Some(peer) = peer {
+        root.get::<SessionLeaveHandler>().call(peer);
+        root.get_mut::<ServerRpcManager>().remove_peer(peer);
+    }
+
+    root.get_mut::<SessionManager>().remove_peer(id);
+}
@@ -0,0 +1,3 @@
+pub mod actors;
+pub mod entry;
+pub mod services;
@@ -0,0 +1,39 @@
+use std::time::Instant;
+
+use aunty::CyclicCtor;
+use giaw_shared::{
+    game::services::replication::{GameSceneClockSample, GameSceneRpcs},
+    util::game::rpc::{RpcAuthority, ServerRpcNode},
+};
+
+// === ServerClock === //
+
+// The server's timeline, in seconds since the scene was created. Clients estimate their offset from
+// it by periodically asking for samples.
+#[derive(Debug)]
+pub struct ServerClock {
+    start: Instant,
+}
+
+impl ServerClock {
+    pub fn new() -> impl CyclicCtor<Self> {
+        |me, _| {
+            me.obj::<ServerRpcNode>()
+                .builder()
+                .sub(GameSceneRpcs::SyncClock)
+                .bind_request(RpcAuthority::AnyPeer, move |_peer, _target, (): ()| {
+                    Ok(GameSceneClockSample {
+                        server_time: me.get::<ServerClock>().server_time(),
+                    })
+                });
+
+            Self {
+                start: Instant::now(),
+            }
+        }
+    }
+
+    pub fn server_time(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+}
@@ -0,0 +1,156 @@
+use aunty::{autoken::ImmutableBorrow, CyclicCtor, Entity, Obj};
+use giaw_shared::util::{
+    game::{
+        rpc::{RpcNodeId, ServerRpcNode},
+        transform::{ColliderManager, Transform},
+    },
+    math::aabb::Aabb,
+};
+use glam::Vec2;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::net::session::SessionState;
+
+use super::replication::{NodeSpawner, TileReplicator};
+
+// === InterestManager === //
+
+#[derive(Debug, Copy, Clone)]
+pub struct InterestConfig {
+    // Nodes within this distance of a peer's player along either axis are replicated to that peer.
+    pub radius: f32,
+
+    // Observed nodes are only forgotten once they're this much further away than `radius` so that
+    // nodes near the edge don't flicker in and out of existence.
+    pub exit_margin: f32,
+}
+
+impl Default for InterestConfig {
+    fn default() -> Self {
+        Self {
+            radius: 32.,
+            exit_margin: 4.,
+        }
+    }
+}
+
+// Decides which of the `NodeSpawner`'s nodes and which parts of the map each peer observes based on
+// the position of its player. Nodes are found through their colliders so nodes without one are only
+// ever observed by their owner. Peers always observe the nodes they own.
+#[derive(Debug)]
+pub struct InterestManager {
+    config: InterestConfig,
+    root: Obj<ServerRpcNode>,
+    spawner: Obj<NodeSpawner>,
+    colliders: Obj<ColliderManager>,
+    tile_replicator: Obj<TileReplicator>,
+
+    // The nodes each peer was interested in as of the last update.
+    observed: FxHashMap<Entity, FxHashSet<RpcNodeId>>,
+}
+
+impl InterestManager {
+    pub fn new(config: InterestConfig) -> impl CyclicCtor<Self> {
+        move |me, _| Self {
+            config,
+            root: me.obj(),
+            spawner: me.obj(),
+            colliders: me.obj(),
+            tile_replicator: me.obj(),
+            observed: FxHashMap::default(),
+        }
+    }
+
+    pub fn config(&self) -> InterestConfig {
+        self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut InterestConfig {
+        &mut self.config
+    }
+
+    // Announces the nodes which entered each peer's area of interest and forgets the ones which
+    // left it. This is called once per tick, after despawns have been processed.
+    pub fn update(&mut self) {
+        // Only peers which know about the scene's root can learn about its nodes.
+        let peers = self.root.get().observers().collect::<FxHashSet<_>>();
+        self.observed.retain(|peer, _| peers.contains(peer));
+
+        for peer in peers {
+            let center = peer
+                .get::<SessionState>()
+                .player
+                .map(|player| player.get::<Transform>().global_pos());
+
+            // Send the map around the peer before the nodes standing on it.
+            if let Some(center) = center {
+                self.tile_replicator
+                    .get_mut()
+                    .stream_chunks(peer, self.enter_aabb(center));
+            }
+
+            let interested = self.interesting_nodes(peer, center);
+            let previous = self.observed.remove(&peer).unwrap_or_default();
+
+            let spawner = self.spawner.get();
+            for &id in interested.difference(&previous) {
+                spawner.announce(peer, id);
+            }
+            for &id in previous.difference(&interested) {
+                spawner.forget(peer, id);
+            }
+            drop(spawner);
+
+            self.observed.insert(peer, interested);
+        }
+    }
+
+    fn enter_aabb(&self, center: Vec2) -> Aabb {
+        Aabb::new_centered(center, Vec2::splat(self.config.radius * 2.))
+    }
+
+    fn exit_aabb(&self, center: Vec2) -> Aabb {
+        Aabb::new_centered(
+            center,
+            Vec2::splat((self.config.radius + self.config.exit_margin) * 2.),
+        )
+    }
+
+    fn interesting_nodes(&self, peer: Entity, center: Option<Vec2>) -> FxHashSet<RpcNodeId> {
+        let spawner = self.spawner.get();
+        let mut interested = spawner.owned_by(peer).collect::<FxHashSet<_>>();
+
+        let Some(center) = center else {
+            return interested;
+        };
+
+        let enter_aabb = self.enter_aabb(center);
+        let exit_aabb = self.exit_aabb(center);
+        let observed = self.observed.get(&peer);
+
+        for (target, _, _) in self.colliders.get().iter_in(exit_aabb) {
+            let loaner = ImmutableBorrow::new();
+            let Some(node) = target.try_get::<ServerRpcNode>(&loaner) else {
+                continue;
+            };
+
+            let id = node.id();
+            if spawner.node(id).is_none() {
+                continue;
+            }
+
+            let pos = target.get::<Transform>().global_pos();
+            let aabb = if observed.is_some_and(|observed| observed.contains(&id)) {
+                exit_aabb
+            } else {
+                enter_aabb
+            };
+
+            if aabb.contains(pos) {
+                interested.insert(id);
+            }
+        }
+
+        interested
+    }
+}
@@ -0,0 +1,258 @@
+use std::mem;
+
+use aunty::{CyclicCtor, Entity, Obj};
+use giaw_shared::{
+    game::services::replication::{
+        GameSceneDespawnNode, GameSceneForgetNode, GameSceneRpcs, GameSceneSetTile,
+        GameSceneSpawnNode, GameSceneTileChunk,
+    },
+    util::{
+        game::{
+            rpc::{RpcNodeId, ServerRpcNode, ServerRpcNodeSender},
+            tile::{LayerIndex, MaterialInfo, TileMap, CHUNK_EDGE},
+            transform::EntityExt,
+        },
+        math::aabb::{Aabb, AabbI},
+    },
+};
+use glam::IVec2;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+// === NodeSpawner === //
+
+// Keeps track of the nodes which can be replicated to clients. Which peers actually learn about
+// each node is decided by the `InterestManager`.
+#[derive(Debug)]
+pub struct NodeSpawner {
+    spawn_sender: ServerRpcNodeSender,
+    despawn_sender: ServerRpcNodeSender,
+    forget_sender: ServerRpcNodeSender,
+    nodes: FxHashMap<RpcNodeId, SpawnedNode>,
+    owned: FxHashMap<Entity, FxHashSet<RpcNodeId>>,
+}
+
+#[derive(Debug)]
+struct SpawnedNode {
+    node: Obj<ServerRpcNode>,
+    kind: String,
+    owner: Option<Entity>,
+}
+
+impl NodeSpawner {
+    pub fn new() -> impl CyclicCtor<Self> {
+        |me, _| {
+            let node = me.obj::<ServerRpcNode>();
+            let builder = node.builder();
+
+            Self {
+                spawn_sender: builder.sub(GameSceneRpcs::SpawnNode).sender(),
+                despawn_sender: builder.sub(GameSceneRpcs::DespawnNode).sender(),
+                forget_sender: builder.sub(GameSceneRpcs::ForgetNode).sender(),
+                nodes: FxHashMap::default(),
+                owned: FxHashMap::default(),
+            }
+        }
+    }
+
+    // Nodes are indexed by the owner they were spawned with.
+    pub fn spawn(&mut self, node: Obj<ServerRpcNode>, kind: String) {
+        let (id, owner) = {
+            let node = node.get();
+            (node.id(), node.owner())
+        };
+
+        if let Some(owner) = owner {
+            self.owned.entry(owner).or_default().insert(id);
+        }
+
+        let replaced = self.nodes.insert(id, SpawnedNode { node, kind, owner });
+        debug_assert!(replaced.is_none());
+    }
+
+    pub fn despawn(&mut self, id: RpcNodeId) {
+        let Some(spawned) = self.nodes.remove(&id) else {
+            return;
+        };
+
+        if let Some(owner) = spawned.owner {
+            if let Some(owned) = self.owned.get_mut(&owner) {
+                owned.remove(&id);
+                if owned.is_empty() {
+                    self.owned.remove(&owner);
+                }
+            }
+        }
+
+        // Only the peers which were told about the node need to hear about its despawn.
+        for peer in spawned.node.get().observers() {
+            self.despawn_sender.send(peer, &GameSceneDespawnNode { id });
+        }
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (RpcNodeId, &Obj<ServerRpcNode>)> + '_ {
+        self.nodes.iter().map(|(&id, spawned)| (id, &spawned.node))
+    }
+
+    pub fn node(&self, id: RpcNodeId) -> Option<&Obj<ServerRpcNode>> {
+        self.nodes.get(&id).map(|spawned| &spawned.node)
+    }
+
+    pub fn owned_by(&self, peer: Entity) -> impl Iterator<Item = RpcNodeId> + '_ {
+        self.owned.get(&peer).into_iter().flatten().copied()
+    }
+
+    // Tells `peer` about the node and catches it up. This must only be called once the node's
+    // entity has been fully constructed so that its catchup packets can be generated.
+    pub fn announce(&self, peer: Entity, id: RpcNodeId) {
+        let Some(spawned) = self.nodes.get(&id) else {
+            return;
+        };
+
+        let owner = {
+            let node = spawned.node.get();
+            if node.is_observed_by(peer) {
+                return;
+            }
+            node.owner()
+        };
+
+        self.spawn_sender.send(
+            peer,
+            &GameSceneSpawnNode {
+                id,
+                kind: spawned.kind.clone(),
+                owned: owner == Some(peer),
+            },
+        );
+        spawned.node.queue_catchup(peer);
+    }
+
+    // Tells `peer` to stop replicating the node. It may be announced to the peer again later.
+    pub fn forget(&self, peer: Entity, id: RpcNodeId) {
+        let Some(spawned) = self.nodes.get(&id) else {
+            return;
+        };
+
+        if spawned.node.get_mut().forget_peer(peer) {
+            self.forget_sender.send(peer, &GameSceneForgetNode { id });
+        }
+    }
+}
+
+// === ReplicatedNode === //
+
+#[derive(Debug)]
+pub struct ReplicatedNode {
+    spawner: Obj<NodeSpawner>,
+    id: RpcNodeId,
+}
+
+impl ReplicatedNode {
+    pub fn new(kind: impl Into<String>) -> impl CyclicCtor<Self> {
+        let kind = kind.into();
+
+        move |me, _| {
+            let spawner = me.deep_obj::<NodeSpawner>();
+            let node = me.obj::<ServerRpcNode>();
+            let id = node.get().id();
+
+            spawner.get_mut().spawn(node, kind);
+
+            Self { spawner, id }
+        }
+    }
+
+    pub fn despawn(&self) {
+        self.spawner.get_mut().despawn(self.id);
+    }
+}
+
+// === TileReplicator === //
+
+// Streams the map's chunks to each peer as they enter its area of interest. Edits are broadcast to
+// every peer since they're cheap and it means that chunks never have to be re-sent.
+#[derive(Debug)]
+pub struct TileReplicator {
+    tile_map: Obj<TileMap>,
+    set_tile_sender: ServerRpcNodeSender,
+    load_chunk_sender: ServerRpcNodeSender,
+    queued_edits: Vec<GameSceneSetTile>,
+    sent_chunks: FxHashMap<Entity, FxHashSet<(LayerIndex, IVec2)>>,
+}
+
+impl TileReplicator {
+    pub fn new() -> impl CyclicCtor<Self> {
+        |me, _| {
+            let node = me.obj::<ServerRpcNode>();
+            let builder = node.builder();
+
+            Self {
+                tile_map: me.obj(),
+                set_tile_sender: builder.sub(GameSceneRpcs::SetTile).sender(),
+                load_chunk_sender: builder.sub(GameSceneRpcs::LoadChunk).sender(),
+                queued_edits: Vec::new(),
+                sent_chunks: FxHashMap::default(),
+            }
+        }
+    }
+
+    pub fn set_tile(&mut self, layer: LayerIndex, pos: IVec2, material: MaterialInfo) {
+        self.tile_map.get_mut().set(layer, pos, material);
+        self.queued_edits.push(GameSceneSetTile {
+            layer: layer.0 as u32,
+            x: pos.x,
+            y: pos.y,
+            material: material.id,
+        });
+    }
+
+    // Sends every chunk overlapping `area`, in actor coordinates, which `peer` hasn't received yet.
+    pub fn stream_chunks(&mut self, peer: Entity, area: Aabb) {
+        let mut tile_map = self.tile_map.get_mut();
+        let sent = self.sent_chunks.entry(peer).or_default();
+
+        for layer in tile_map.layers() {
+            let tiles = tile_map.actor_aabb_to_tile(layer, area);
+            let chunks = AabbI {
+                min: tiles.min.div_euclid(IVec2::splat(CHUNK_EDGE)),
+                max: tiles.max.div_euclid(IVec2::splat(CHUNK_EDGE)),
+            };
+            let data = &mut tile_map.layers[layer.0].data;
+
+            for pos in chunks.inclusive().iter() {
+                // (empty chunks are marked as sent too since the peer hears about every edit)
+                if !sent.insert((layer, pos)) {
+                    continue;
+                }
+
+                let Some(chunk) = data.chunk(pos) else {
+                    continue;
+                };
+
+                self.load_chunk_sender.send(
+                    peer,
+                    &GameSceneTileChunk {
+                        layer: layer.0 as u32,
+                        x: pos.x,
+                        y: pos.y,
+                        data: chunk.to_vec(),
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn forget_peer(&mut self, peer: Entity) {
+        self.sent_chunks.remove(&peer);
+    }
+
+    // Broadcasts the edits made since the last flush as a single message.
+    pub fn flush(&mut self) {
+        if self.queued_edits.is_empty() {
+            return;
+        }
+
+        self.set_tile_sender
+            .broadcast(&mem::take(&mut self.queued_edits));
+    }
+}
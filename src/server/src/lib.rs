@@ -1 +1,3 @@
+pub mod engine;
+pub mod game;
 pub mod net;
@@ -1,2 +1,3 @@
+pub mod policy;
 pub mod session;
 pub mod transport;
@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+use giaw_shared::util::game::rpc::RpcError;
+
+use super::session::SessionState;
+
+#[derive(Debug, Clone)]
+pub struct PeerPolicy {
+    pub max_strikes: u32,
+
+    // One strike is forgiven for every `strike_decay` that passes so that long sessions don't
+    // accumulate strikes from the occasional benign race.
+    pub strike_decay: Duration,
+}
+
+impl Default for PeerPolicy {
+    fn default() -> Self {
+        Self {
+            max_strikes: 8,
+            strike_decay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl PeerPolicy {
+    // Each of these methods returns the reason for which the peer should be kicked, if any.
+
+    pub fn judge_malformed_packet(&self, err: &anyhow::Error) -> Option<String> {
+        // We can't trust anything about a peer whose packets we can't even decode.
+        Some(format!("sent a malformed packet: {err}"))
+    }
+
+    pub fn judge_rpc_errors(
+        &self,
+        session: &mut SessionState,
+        errors: &[RpcError],
+    ) -> Option<String> {
+        for error in errors {
+            log::warn!("Socket {:?} caused an RPC error: {error}", session.id);
+
+            match error {
+                // A well-behaved client can never produce these.
+                RpcError::NullNodeId | RpcError::UnexpectedCatchup | RpcError::Decode { .. } => {
+                    return Some(error.to_string());
+                }
+
+                // These can be caused by the peer racing against a node's despawn, a call's
+                // timeout or an ownership change so we give it some leeway.
+                RpcError::UnknownNode { .. }
+                | RpcError::UnknownPath { .. }
+                | RpcError::UnknownReference { .. }
+                | RpcError::Handler { .. }
+                | RpcError::UnknownCall { .. }
+                | RpcError::Unauthorized { .. } => {
+                    self.add_strike(session, Instant::now());
+                }
+            }
+        }
+
+        (session.strikes >= self.max_strikes)
+            .then(|| format!("caused too many RPC errors ({} strike(s))", session.strikes))
+    }
+
+    fn add_strike(&self, session: &mut SessionState, now: Instant) {
+        if session.strikes == 0 {
+            session.strikes_decayed_at = now;
+        } else {
+            let elapsed = now.saturating_duration_since(session.strikes_decayed_at);
+            let forgiven = (elapsed.as_secs_f64() / self.strike_decay.as_secs_f64()) as u32;
+            let forgiven = forgiven.min(session.strikes);
+
+            session.strikes -= forgiven;
+            session.strikes_decayed_at = if session.strikes == 0 {
+                now
+            } else {
+                session.strikes_decayed_at + self.strike_decay * forgiven
+            };
+        }
+
+        session.strikes += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use giaw_shared::util::net::transport::QuadPeerId;
+
+    use super::*;
+
+    #[test]
+    fn strikes_decay_over_time() {
+        let policy = PeerPolicy::default();
+        let start = Instant::now();
+        let mut session = SessionState {
+            id: QuadPeerId(0),
+            strikes: 0,
+            strikes_decayed_at: start,
+            player: None,
+        };
+
+        for _ in 0..3 {
+            policy.add_strike(&mut session, start);
+        }
+        assert_eq!(session.strikes, 3);
+
+        // Two strikes are forgiven before the new one is added.
+        policy.add_strike(&mut session, start + policy.strike_decay * 2);
+        assert_eq!(session.strikes, 2);
+
+        // A long period of good behavior clears the record entirely.
+        policy.add_strike(&mut session, start + policy.strike_decay * 100);
+        assert_eq!(session.strikes, 1);
+    }
+}
@@ -1,25 +1,82 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Instant,
+};
 
-use aunty::{Entity, StrongEntity};
+use aunty::{delegate, Entity, StrongEntity};
+use bytes::Bytes;
+use giaw_shared::util::{
+    game::rpc::decode_packet,
+    net::handshake::{HandshakeHello, HandshakeResponse},
+};
 
 use super::transport::QuadPeerId;
 
-#[derive(Debug, Default)]
+// === Handlers === //
+
+delegate! {
+    pub fn SessionJoinHandler(session: Entity)
+}
+
+delegate! {
+    pub fn SessionLeaveHandler(session: Entity)
+}
+
+// === SessionManager === //
+
+#[derive(Debug)]
 pub struct SessionManager {
+    hello: HandshakeHello,
+    handshaking: HashSet<QuadPeerId>,
     sessions: HashMap<QuadPeerId, StrongEntity>,
 }
 
 impl SessionManager {
+    pub fn new(hello: HandshakeHello) -> Self {
+        Self {
+            hello,
+            handshaking: HashSet::default(),
+            sessions: HashMap::default(),
+        }
+    }
+
     pub fn add_peer(&mut self, id: QuadPeerId) {
-        self.sessions.insert(
-            id,
-            StrongEntity::new()
-                .with_debug_label(format_args!("peer @ {id:?}"))
-                .with(SessionState { id }),
-        );
+        self.handshaking.insert(id);
+    }
+
+    pub fn is_handshaking(&self, id: QuadPeerId) -> bool {
+        self.handshaking.contains(&id)
+    }
+
+    pub fn complete_handshake(&mut self, id: QuadPeerId, data: &Bytes) -> HandshakeResponse {
+        assert!(self.handshaking.remove(&id));
+
+        let result = decode_packet::<HandshakeHello>(data)
+            .map_err(|err| format!("malformed handshake: {err}"))
+            .and_then(|hello| hello.validate(&self.hello));
+
+        match result {
+            Ok(()) => {
+                self.sessions.insert(
+                    id,
+                    StrongEntity::new()
+                        .with_debug_label(format_args!("peer @ {id:?}"))
+                        .with(SessionState {
+                            id,
+                            strikes: 0,
+                            strikes_decayed_at: Instant::now(),
+                            player: None,
+                        }),
+                );
+
+                HandshakeResponse::Accepted
+            }
+            Err(reason) => HandshakeResponse::Rejected { reason },
+        }
     }
 
     pub fn remove_peer(&mut self, id: QuadPeerId) {
+        self.handshaking.remove(&id);
         self.sessions.remove(&id);
     }
 
@@ -27,6 +84,10 @@ impl SessionManager {
         self.sessions[&id].entity()
     }
 
+    pub fn try_peer_by_id(&self, id: QuadPeerId) -> Option<Entity> {
+        self.sessions.get(&id).map(StrongEntity::entity)
+    }
+
     pub fn peers(&self) -> impl Iterator<Item = Entity> + '_ {
         self.sessions.values().map(StrongEntity::entity)
     }
@@ -35,4 +96,7 @@ impl SessionManager {
 #[derive(Debug)]
 pub struct SessionState {
     pub id: QuadPeerId,
+    pub strikes: u32,
+    pub strikes_decayed_at: Instant,
+    pub player: Option<Entity>,
 }
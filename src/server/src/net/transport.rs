@@ -1,10 +1,28 @@
-use std::{collections::HashMap, net::SocketAddr};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use bytes::{Buf, BufMut, Bytes, BytesMut};
+use bytes::{Bytes, BytesMut};
 use futures::SinkExt;
+use giaw_shared::util::net::{
+    datagram::{Datagram, MAX_DATAGRAM_SIZE},
+    framing::{Frame, FrameCodec, DEFAULT_MAX_FRAME_SIZE},
+    transport::ServerTransport,
+};
 use tokio::{
-    net::TcpListener,
-    sync::mpsc::{channel, error::TryRecvError, unbounded_channel, Receiver, UnboundedSender},
+    net::{TcpListener, UdpSocket},
+    sync::{
+        mpsc::{
+            channel,
+            error::{SendError, TryRecvError},
+            unbounded_channel, Receiver, Sender, UnboundedSender,
+        },
+        Notify,
+    },
+    time::{interval, Instant, MissedTickBehavior},
 };
 use tokio_stream::StreamExt;
 use tokio_util::codec::{Decoder, Encoder, Framed};
@@ -13,30 +31,33 @@ use tokio_util::codec::{Decoder, Encoder, Framed};
 
 const SERVER_EVENT_CHANNEL_SIZE: usize = 16;
 
-#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
-pub struct QuadPeerId(u64);
+pub use giaw_shared::util::net::transport::{QuadPeerId, QuadServerEvent};
 
-#[derive(Debug)]
-pub enum QuadServerEvent {
-    PeerConnected {
-        id: QuadPeerId,
-        addr: SocketAddr,
-    },
-    PeerData {
-        id: QuadPeerId,
-        data: Bytes,
-    },
-    PeerDisconnect {
-        id: QuadPeerId,
-        err: Option<anyhow::Error>,
-    },
+#[derive(Debug, Clone)]
+pub struct QuadServerConfig {
+    pub max_frame_size: usize,
+    pub heartbeat_interval: Duration,
+    pub idle_timeout: Duration,
+}
+
+impl Default for QuadServerConfig {
+    fn default() -> Self {
+        Self {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            heartbeat_interval: Duration::from_secs(1),
+            idle_timeout: Duration::from_secs(10),
+        }
+    }
 }
 
 // QuadServer
 #[derive(Debug)]
 pub struct QuadServer {
+    waker: Arc<Notify>,
     events: Receiver<InternalServerEvent>,
     sockets: HashMap<QuadPeerId, SocketState>,
+    datagrams: Option<Arc<UdpSocket>>,
+    tokens: Arc<Mutex<HashMap<u64, QuadPeerId>>>,
 }
 
 enum InternalServerEvent {
@@ -48,6 +69,15 @@ enum InternalServerEvent {
         id: QuadPeerId,
         data: Bytes,
     },
+    PeerDatagram {
+        id: QuadPeerId,
+        addr: SocketAddr,
+        datagram: Datagram,
+    },
+    PeerRtt {
+        id: QuadPeerId,
+        rtt: Duration,
+    },
     PeerDisconnect {
         id: QuadPeerId,
         err: Option<anyhow::Error>,
@@ -55,15 +85,103 @@ enum InternalServerEvent {
     ServerError(anyhow::Error),
 }
 
+#[derive(Clone)]
+struct EventSender {
+    sender: Sender<InternalServerEvent>,
+    waker: Arc<Notify>,
+}
+
+impl EventSender {
+    async fn send(&self, event: InternalServerEvent) -> Result<(), SendError<InternalServerEvent>> {
+        self.sender.send(event).await?;
+        self.waker.notify_one();
+        Ok(())
+    }
+
+    async fn closed(&self) {
+        self.sender.closed().await
+    }
+}
+
+#[derive(Debug)]
+enum SocketCommand {
+    Send(Bytes),
+    Kick(String),
+}
+
 #[derive(Debug)]
 struct SocketState {
     addr: SocketAddr,
-    sender: UnboundedSender<Bytes>,
+    sender: UnboundedSender<SocketCommand>,
+    rtt: Option<Duration>,
+    token: Option<u64>,
+
+    // The address from which the peer last sent us a valid datagram.
+    datagram_addr: Option<SocketAddr>,
 }
 
 impl QuadServer {
-    pub fn new(listener: TcpListener) -> Self {
+    // If `datagrams` is provided, peers are told how to send datagrams to it and data sent with
+    // `send_unreliable` goes through it once they have. Otherwise, everything goes through the
+    // peer's TCP stream.
+    pub fn new(
+        listener: TcpListener,
+        datagrams: Option<UdpSocket>,
+        config: QuadServerConfig,
+    ) -> Self {
         let (server_send, server_recv) = channel(SERVER_EVENT_CHANNEL_SIZE);
+        let waker = Arc::new(Notify::new());
+        let server_send = EventSender {
+            sender: server_send,
+            waker: waker.clone(),
+        };
+        let datagrams = datagrams.map(Arc::new);
+        let tokens = Arc::new(Mutex::new(HashMap::new()));
+
+        // Spin up a thread to receive datagrams
+        if let Some(datagrams) = datagrams.clone() {
+            let tokens = tokens.clone();
+            let server_send = server_send.clone();
+
+            tokio::spawn(async move {
+                let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE + 1];
+
+                loop {
+                    let (len, addr) = tokio::select! {
+                        res = datagrams.recv_from(&mut buffer) => match res {
+                            Ok(res) => res,
+                            // These are usually just reports of datagrams which failed to reach a
+                            // peer, which we can't do anything about.
+                            Err(_) => continue,
+                        },
+                        _ = server_send.closed() => break,
+                    };
+
+                    // Anyone can send us datagrams so ones which are malformed or which don't carry
+                    // a live peer's token are silently dropped.
+                    if len > MAX_DATAGRAM_SIZE {
+                        continue;
+                    }
+
+                    let Ok((token, datagram)) =
+                        Datagram::decode_client(Bytes::copy_from_slice(&buffer[..len]))
+                    else {
+                        continue;
+                    };
+
+                    let Some(id) = tokens.lock().unwrap().get(&token).copied() else {
+                        continue;
+                    };
+
+                    let _ = server_send
+                        .send(InternalServerEvent::PeerDatagram { id, addr, datagram })
+                        .await;
+                }
+            });
+        }
+
+        let accept_tokens = tokens.clone();
+        let has_datagrams = datagrams.is_some();
 
         tokio::spawn(async move {
             let mut id_gen = 0u64;
@@ -87,12 +205,23 @@ impl QuadServer {
                 };
 
                 // Initialize state for the socket
-                let mut stream = Framed::new(stream, QuadNetCodec);
+                let mut stream =
+                    Framed::new(stream, QuadNetCodec(FrameCodec::new(config.max_frame_size)));
                 let (socket_send, mut socket_recv) = unbounded_channel();
                 let id = QuadPeerId(id_gen);
                 let server_send = server_send.clone();
                 id_gen += 1;
 
+                // Tokens must be hard for other peers to guess so we draw them from the OS's
+                // secure random number generator.
+                let token = has_datagrams.then(|| {
+                    let mut token = [0; 8];
+                    getrandom::getrandom(&mut token).expect("failed to generate a datagram token");
+                    let token = u64::from_le_bytes(token);
+                    accept_tokens.lock().unwrap().insert(token, id);
+                    token
+                });
+
                 // Notify the main thread of its existence
                 let _ = server_send
                     .send(InternalServerEvent::PeerConnected {
@@ -100,76 +229,134 @@ impl QuadServer {
                         state: SocketState {
                             addr,
                             sender: socket_send,
+                            rtt: None,
+                            token,
+                            datagram_addr: None,
                         },
                     })
                     .await;
 
                 // Spin up a thread to process its packets
+                let heartbeat_interval = config.heartbeat_interval;
+                let idle_timeout = config.idle_timeout;
+
                 tokio::spawn(async move {
-                    loop {
-                        tokio::select! {
-                            // A network client wants us to do something.
-                            ev = stream.next() => {
-                                match ev {
-                                    // We received a packet.
-                                    Some(Ok(data)) => {
-                                        let _ = server_send.send(InternalServerEvent::PeerData { id, data }).await;
-                                    },
-
-                                    // We failed to poll the socket.
-                                    Some(Err(err)) => {
-                                        // Notify the main thread...
-                                        let _ = server_send.send(
-                                            InternalServerEvent::PeerDisconnect {
-                                                id,
-                                                err: Some(err),
-                                            },
-                                        ).await;
-
-                                        // And close the socket.
-                                        break;
-                                    },
-
-                                    // The socket closed naturally
-                                    None => {
-                                        // Notify the main thread...
-                                        let _ = server_send.send(
-                                            InternalServerEvent::PeerDisconnect {
-                                                id,
-                                                err: None,
-                                            },
-                                        ).await;
-
-                                        // And close the socket.
-                                        break;
-                                    },
-                                }
-                            },
-
-                            // The main thread wants us to do something.
-                            ev = socket_recv.recv() => {
-                                let Some(ev) = ev else {
-                                    // The main thread wants this client kicked.
-                                    break
-                                };
-
-                                if let Err(err) = stream.send(&ev).await {
-                                    // A fatal ocurred while trying to communicate with this peer.
-                                    // Notify the main thread...
-                                    let _ = server_send.send(
-                                        InternalServerEvent::PeerDisconnect {
-                                            id,
-                                            err: Some(err),
+                    let mut heartbeat = interval(heartbeat_interval);
+                    heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+                    let mut last_recv = Instant::now();
+                    let mut ping_gen = 0u64;
+                    let mut pending_ping = None::<(u64, Instant)>;
+
+                    let err = 'session: {
+                        // Tell the peer how to label its datagrams.
+                        if let Some(token) = token {
+                            if let Err(err) = stream.send(Frame::DatagramToken(token)).await {
+                                break 'session Some(err);
+                            }
+                        }
+
+                        loop {
+                            tokio::select! {
+                                // A network client wants us to do something.
+                                ev = stream.next() => {
+                                    match ev {
+                                        // We received a frame.
+                                        Some(Ok(frame)) => {
+                                            last_recv = Instant::now();
+
+                                            match frame {
+                                                Frame::Data(data) => {
+                                                    let _ = server_send.send(InternalServerEvent::PeerData { id, data }).await;
+                                                }
+                                                Frame::Ping(nonce) => {
+                                                    if let Err(err) = stream.send(Frame::Pong(nonce)).await {
+                                                        break Some(err);
+                                                    }
+                                                }
+                                                Frame::Disconnect(_) => {
+                                                    // The peer is leaving gracefully.
+                                                    break None;
+                                                }
+                                                Frame::DatagramToken(_) => {
+                                                    break Some(anyhow::anyhow!(
+                                                        "peer sent a datagram token"
+                                                    ));
+                                                }
+                                                Frame::Pong(nonce) => {
+                                                    // Pongs for anything but the most recent ping are
+                                                    // ignored.
+                                                    if let Some((expected, sent_at)) = pending_ping {
+                                                        if nonce == expected {
+                                                            pending_ping = None;
+
+                                                            let _ = server_send.send(InternalServerEvent::PeerRtt {
+                                                                id,
+                                                                rtt: sent_at.elapsed(),
+                                                            }).await;
+                                                        }
+                                                    }
+                                                }
+                                            }
                                         },
-                                    ).await;
 
-                                    // And close the socket.
-                                    break;
-                                }
-                            },
+                                        // We failed to poll the socket.
+                                        Some(Err(err)) => break Some(err),
+
+                                        // The socket closed naturally
+                                        None => break None,
+                                    }
+                                },
+
+                                // The main thread wants us to do something.
+                                ev = socket_recv.recv() => {
+                                    let Some(ev) = ev else {
+                                        // The `QuadServer` was dropped.
+                                        return;
+                                    };
+
+                                    match ev {
+                                        SocketCommand::Send(data) => {
+                                            if let Err(err) = stream.send(Frame::Data(data)).await {
+                                                // A fatal ocurred while trying to communicate with this peer.
+                                                break Some(err);
+                                            }
+                                        }
+                                        SocketCommand::Kick(reason) => {
+                                            // The main thread wants this client kicked. We don't care
+                                            // whether the peer actually receives the reason.
+                                            let _ = stream.send(Frame::Disconnect(reason.clone())).await;
+                                            break Some(anyhow::anyhow!("peer was kicked: {reason}"));
+                                        }
+                                    }
+                                },
+
+                                // It's time to check up on the peer.
+                                _ = heartbeat.tick() => {
+                                    if last_recv.elapsed() > idle_timeout {
+                                        break Some(anyhow::anyhow!(
+                                            "peer timed out after not sending anything for {idle_timeout:?}"
+                                        ));
+                                    }
+
+                                    let nonce = ping_gen;
+                                    ping_gen += 1;
+                                    pending_ping = Some((nonce, Instant::now()));
+
+                                    if let Err(err) = stream.send(Frame::Ping(nonce)).await {
+                                        break Some(err);
+                                    }
+                                },
+                            }
                         }
-                    }
+                    };
 
+                    // Notify the main thread...
+                    let _ = server_send
+                        .send(InternalServerEvent::PeerDisconnect { id, err })
+                        .await;
+
+                    // And close the socket.
                     drop(stream);
                 });
             }
@@ -178,12 +365,22 @@ impl QuadServer {
         });
 
         Self {
+            waker,
             events: server_recv,
             sockets: HashMap::default(),
+            datagrams,
+            tokens,
         }
     }
 
-    pub fn poll(&mut self) -> anyhow::Result<Vec<QuadServerEvent>> {
+    // The waker is notified every time a new event becomes available to `poll`.
+    pub fn waker(&self) -> Arc<Notify> {
+        self.waker.clone()
+    }
+}
+
+impl ServerTransport for QuadServer {
+    fn poll(&mut self) -> anyhow::Result<Vec<QuadServerEvent>> {
         let mut events = Vec::new();
 
         loop {
@@ -197,7 +394,7 @@ impl QuadServer {
                 InternalServerEvent::PeerConnected { id, state } => {
                     events.push(QuadServerEvent::PeerConnected {
                         id,
-                        addr: state.addr,
+                        addr: Some(state.addr),
                     });
 
                     self.sockets.insert(id, state);
@@ -205,9 +402,29 @@ impl QuadServer {
                 InternalServerEvent::PeerData { id, data } => {
                     events.push(QuadServerEvent::PeerData { id, data });
                 }
+                InternalServerEvent::PeerDatagram { id, addr, datagram } => {
+                    let Some(socket) = self.sockets.get_mut(&id) else {
+                        continue;
+                    };
+
+                    // Follow the peer around if its address changes.
+                    socket.datagram_addr = Some(addr);
+
+                    if let Datagram::Data(data) = datagram {
+                        events.push(QuadServerEvent::PeerUnreliableData { id, data });
+                    }
+                }
+                InternalServerEvent::PeerRtt { id, rtt } => {
+                    if let Some(socket) = self.sockets.get_mut(&id) {
+                        socket.rtt = Some(rtt);
+                    }
+                }
                 InternalServerEvent::PeerDisconnect { id, err } => {
                     events.push(QuadServerEvent::PeerDisconnect { id, err });
-                    self.sockets.remove(&id);
+
+                    if let Some(token) = self.sockets.remove(&id).and_then(|socket| socket.token) {
+                        self.tokens.lock().unwrap().remove(&token);
+                    }
                 }
                 InternalServerEvent::ServerError(err) => return Err(err),
             }
@@ -216,43 +433,60 @@ impl QuadServer {
         Ok(events)
     }
 
-    pub fn send(&mut self, id: QuadPeerId, data: Bytes) {
+    fn send(&mut self, id: QuadPeerId, data: Bytes) {
         if let Some(socket) = self.sockets.get(&id) {
-            let _ = socket.sender.send(data);
+            let _ = socket.sender.send(SocketCommand::Send(data));
+        }
+    }
+
+    fn send_unreliable(&mut self, id: QuadPeerId, data: Bytes) {
+        let Some(socket) = self.sockets.get(&id) else {
+            return;
+        };
+
+        if let (Some(datagrams), Some(addr)) = (&self.datagrams, socket.datagram_addr) {
+            if let Some(datagram) = Datagram::Data(data.clone()).encode_server() {
+                // Datagrams which can't be sent right away are treated like any other lost
+                // datagram.
+                let _ = datagrams.try_send_to(&datagram, addr);
+                return;
+            }
+        }
+
+        // The peer hasn't told us where to send datagrams yet or the data is too large for one so
+        // we fall back to its stream.
+        self.send(id, data);
+    }
+
+    fn rtt(&self, id: QuadPeerId) -> Option<Duration> {
+        self.sockets.get(&id).and_then(|socket| socket.rtt)
+    }
+
+    fn kick(&mut self, id: QuadPeerId, reason: &str) {
+        // The socket stays registered until its task reports the disconnect.
+        if let Some(socket) = self.sockets.get(&id) {
+            let _ = socket.sender.send(SocketCommand::Kick(reason.to_string()));
         }
     }
 }
 
 // === Framing === //
 
-struct QuadNetCodec;
+struct QuadNetCodec(FrameCodec);
 
 impl Decoder for QuadNetCodec {
-    type Item = Bytes;
+    type Item = Frame;
     type Error = anyhow::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let Some(packet_len) = src.first().map(|v| *v as usize) else {
-            return Ok(None);
-        };
-
-        if src.len() <= packet_len {
-            return Ok(None);
-        }
-
-        let packet = src.clone().freeze().slice(1..).slice(..packet_len);
-        src.advance(packet_len + 1);
-
-        Ok(Some(packet))
+        self.0.decode(src)
     }
 }
 
-impl<'a> Encoder<&'a [u8]> for QuadNetCodec {
+impl Encoder<Frame> for QuadNetCodec {
     type Error = anyhow::Error;
 
-    fn encode(&mut self, item: &'a [u8], dst: &mut BytesMut) -> Result<(), Self::Error> {
-        dst.put_u8(u8::try_from(item.len()).unwrap());
-        dst.put(item);
-        Ok(())
+    fn encode(&mut self, item: Frame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.0.encode(&item, dst)
     }
 }
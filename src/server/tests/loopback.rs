@@ -0,0 +1,288 @@
+use std::collections::BTreeSet;
+
+use aunty::{Entity, StrongEntity};
+use bytes::Bytes;
+use giaw_server::{
+    game::{
+        entry::{create_game, flush_net_queues, process_net_events, TICK_RATE},
+        services::replication::NodeSpawner,
+    },
+    net::session::SessionManager,
+};
+use giaw_shared::{
+    game::{
+        scene::{create_game_scene, populate_game_scene},
+        services::replication::{
+            rpc_schema_fingerprint, GameSceneDespawnNode, GameSceneForgetNode, GameSceneRpcs,
+            GameSceneSetTile, GameSceneSpawnNode, GameSceneTileChunk,
+        },
+    },
+    util::{
+        game::{
+            actors::{ActorManager, DespawnHandler, UpdateHandler},
+            rpc::{
+                decode_packet, encode_packet, ClientRpcManager, ClientRpcNode, RpcAuthority,
+                RpcError, RpcNodeId, RpcPacket,
+            },
+            tile::{LayerIndex, TileMap, CHUNK_AREA},
+            transform::Transform,
+        },
+        net::{
+            handshake::{HandshakeHello, HandshakeResponse},
+            loopback::{LoopbackClient, LoopbackConnector, LoopbackServer},
+            transport::{ClientTransport, QuadClientEvent},
+        },
+    },
+};
+use glam::IVec2;
+use rustc_hash::FxHashMap;
+
+// === Headless client === //
+
+// A client which replicates the scene's nodes and tiles without any of the real client's rendering
+// or prediction. Spawned nodes are bare so messages sent to their paths are expected to fail.
+struct TestClient {
+    scene: StrongEntity,
+    transport: LoopbackClient,
+    accepted: bool,
+}
+
+#[derive(Debug, Default)]
+struct TestNodes {
+    spawned: FxHashMap<RpcNodeId, Entity>,
+}
+
+impl TestClient {
+    fn connect(connector: &LoopbackConnector) -> Self {
+        let scene = create_game_scene(None)
+            .with(ClientRpcManager::default())
+            .with_cyclic(ClientRpcNode::new(RpcNodeId::ROOT))
+            .with(TestNodes::default());
+
+        populate_game_scene(scene.entity(), |_, material| material, |_, item| item);
+
+        let me = scene.entity();
+        let node = scene.obj::<ClientRpcNode>();
+
+        node.builder().sub(GameSceneRpcs::SpawnNode).bind_message(
+            RpcAuthority::ServerOnly,
+            move |(), _, msg: GameSceneSpawnNode| {
+                let entity = me
+                    .get::<ActorManager>()
+                    .spawn()
+                    .with_cyclic(Transform::new(Some(me.obj())))
+                    .with_cyclic(ClientRpcNode::new(msg.id))
+                    .with_cyclic(|me, _| {
+                        DespawnHandler::new(move || me.get::<ClientRpcNode>().despawn())
+                    });
+
+                let replaced = me.get_mut::<TestNodes>().spawned.insert(msg.id, entity);
+                anyhow::ensure!(replaced.is_none(), "node {:?} was spawned twice", msg.id);
+                Ok(())
+            },
+        );
+
+        node.builder().sub(GameSceneRpcs::DespawnNode).bind_message(
+            RpcAuthority::ServerOnly,
+            move |(), _, msg: GameSceneDespawnNode| remove_node(me, msg.id),
+        );
+
+        node.builder().sub(GameSceneRpcs::ForgetNode).bind_message(
+            RpcAuthority::ServerOnly,
+            move |(), _, msg: GameSceneForgetNode| remove_node(me, msg.id),
+        );
+
+        node.builder().sub(GameSceneRpcs::LoadChunk).bind_message(
+            RpcAuthority::ServerOnly,
+            move |(), _, chunk: GameSceneTileChunk| {
+                let data = <&[u16; CHUNK_AREA as usize]>::try_from(chunk.data.as_slice())?;
+                me.get_mut::<TileMap>().layers[chunk.layer as usize]
+                    .data
+                    .set_chunk(IVec2::new(chunk.x, chunk.y), data);
+                Ok(())
+            },
+        );
+
+        node.builder().sub(GameSceneRpcs::SetTile).bind_message(
+            RpcAuthority::ServerOnly,
+            move |(), _, edits: Vec<GameSceneSetTile>| {
+                let mut tile_map = me.get_mut::<TileMap>();
+                for edit in edits {
+                    let material = tile_map.materials.get().get(edit.material);
+                    tile_map.set(
+                        LayerIndex(edit.layer as usize),
+                        IVec2::new(edit.x, edit.y),
+                        material,
+                    );
+                }
+                Ok(())
+            },
+        );
+
+        let mut transport = connector.connect();
+        transport.send(&encode_packet(&HandshakeHello::new(
+            rpc_schema_fingerprint(),
+        )));
+
+        Self {
+            scene,
+            transport,
+            accepted: false,
+        }
+    }
+
+    fn pump(&mut self) {
+        for event in self.transport.poll() {
+            let packet = match event {
+                QuadClientEvent::Data(packet) | QuadClientEvent::UnreliableData(packet) => packet,
+                QuadClientEvent::Kicked(reason) => panic!("client was kicked: {reason}"),
+                QuadClientEvent::Disconnect(err) => panic!("client was disconnected: {err:?}"),
+            };
+
+            if !self.accepted {
+                match decode_packet::<HandshakeResponse>(&packet).unwrap() {
+                    HandshakeResponse::Accepted => self.accepted = true,
+                    HandshakeResponse::Rejected { reason } => {
+                        panic!("server rejected the handshake: {reason}")
+                    }
+                }
+                continue;
+            }
+
+            self.process_rpc_packet(&packet);
+        }
+
+        self.scene.get::<ActorManager>().process_despawns();
+
+        let manager = self.scene.obj::<ClientRpcManager>();
+        manager.run_flushes();
+        for ((), _delivery, packet) in manager.get_mut().drain_queues() {
+            self.transport.send(&encode_packet(&packet));
+        }
+    }
+
+    fn process_rpc_packet(&self, packet: &Bytes) {
+        let packet = decode_packet::<RpcPacket>(packet).unwrap();
+        let errors = self
+            .scene
+            .obj::<ClientRpcManager>()
+            .process_packet((), &packet);
+
+        for error in errors {
+            assert!(
+                matches!(error, RpcError::UnknownPath { .. }),
+                "unexpected RPC error: {error}"
+            );
+        }
+    }
+
+    fn spawned(&self) -> BTreeSet<u64> {
+        self.scene
+            .get::<TestNodes>()
+            .spawned
+            .keys()
+            .map(|id| id.0.get())
+            .collect()
+    }
+}
+
+fn remove_node(scene: Entity, id: RpcNodeId) -> anyhow::Result<()> {
+    let entity = scene
+        .get_mut::<TestNodes>()
+        .spawned
+        .remove(&id)
+        .ok_or_else(|| anyhow::anyhow!("attempted to remove unknown node {id:?}"))?;
+
+    scene
+        .get::<ActorManager>()
+        .queue_despawn(&entity.get::<Transform>());
+
+    Ok(())
+}
+
+// === Helpers === //
+
+fn step(server: Entity, clients: &mut [&mut TestClient]) {
+    server.get::<UpdateHandler>().call(1. / TICK_RATE as f32);
+    process_net_events(server);
+    flush_net_queues(server);
+
+    for client in clients {
+        client.pump();
+    }
+}
+
+fn observed_by(server: Entity, client: &TestClient) -> BTreeSet<u64> {
+    let peer = server
+        .get::<SessionManager>()
+        .peer_by_id(client.transport.id());
+
+    server
+        .get::<NodeSpawner>()
+        .nodes()
+        .filter(|(_, node)| node.get().is_observed_by(peer))
+        .map(|(id, _)| id.0.get())
+        .collect()
+}
+
+fn chunks(tile_map: &mut TileMap) -> Vec<(usize, IVec2, Vec<u16>)> {
+    let mut chunks = Vec::new();
+
+    for layer in tile_map.layers() {
+        let data = &mut tile_map.layers[layer.0].data;
+        let mut positions = data.chunks().collect::<Vec<_>>();
+        positions.sort_by_key(|pos| (pos.x, pos.y));
+
+        for pos in positions {
+            chunks.push((layer.0, pos, data.chunk(pos).unwrap().to_vec()));
+        }
+    }
+
+    chunks
+}
+
+// === Tests === //
+
+#[test]
+fn clients_replicate_the_server_scene() {
+    let transport = LoopbackServer::default();
+    let connector = transport.connector();
+    let server = create_game(None, Box::new(transport));
+
+    let mut alice = TestClient::connect(&connector);
+    let mut bob = TestClient::connect(&connector);
+
+    for _ in 0..4 {
+        step(server.entity(), &mut [&mut alice, &mut bob]);
+    }
+
+    assert!(alice.accepted && bob.accepted);
+
+    // Both players spawn next to each other so each client should see both of them.
+    for client in [&alice, &bob] {
+        let observed = observed_by(server.entity(), client);
+        assert_eq!(observed.len(), 2);
+        assert_eq!(client.spawned(), observed);
+    }
+
+    // The map should have been caught up as well.
+    let server_chunks = chunks(&mut server.get_mut::<TileMap>());
+    assert!(!server_chunks.is_empty());
+    for client in [&alice, &bob] {
+        assert_eq!(
+            chunks(&mut client.scene.get_mut::<TileMap>()),
+            server_chunks
+        );
+    }
+
+    // Once a client leaves, its player should disappear for everyone else.
+    drop(alice);
+
+    for _ in 0..4 {
+        step(server.entity(), &mut [&mut bob]);
+    }
+
+    let observed = observed_by(server.entity(), &bob);
+    assert_eq!(observed.len(), 1);
+    assert_eq!(bob.spawned(), observed);
+}
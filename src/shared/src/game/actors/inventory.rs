@@ -1,7 +1,8 @@
 use aunty::{CyclicCtor, Entity, Obj, StrongEntity};
 use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
 
-use crate::util::game::{actors::ActorManager, transform::Transform};
+use crate::util::game::{actors::ActorManager, tile::MaterialInfo, transform::Transform};
 
 // === ItemRegistry === //
 
@@ -22,6 +23,26 @@ impl ItemRegistry {
     }
 }
 
+// === Item Descriptors === //
+
+// Attached to the descriptors of items which place a tile when used.
+#[derive(Debug, Copy, Clone)]
+pub struct BlockItemDescriptor {
+    pub material: MaterialInfo,
+}
+
+// Attached to the descriptors of items which launch their user towards the cursor.
+#[derive(Debug, Copy, Clone)]
+pub struct LaunchItemDescriptor {
+    pub strength: f32,
+}
+
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub enum InteractMode {
+    Build,
+    Break,
+}
+
 // === InventoryData === //
 
 #[derive(Debug)]
@@ -1,19 +1,93 @@
-use aunty::{CyclicCtor, Obj};
+use aunty::{autoken::ImmutableBorrow, CyclicCtor, Obj};
 use glam::Vec2;
+use serde::{Deserialize, Serialize};
 
-use crate::util::{
+use crate::{
     game::{
-        kinematic::{filter_descendants, KinematicManager},
-        transform::{Collider, EntityExt, Transform},
+        actors::inventory::{InteractMode, InventoryData, LaunchItemDescriptor},
+        services::replication::GameSceneSetTile,
+    },
+    rpc_path,
+    util::{
+        game::{
+            kinematic::{filter_descendants, KinematicManager},
+            transform::{Collider, EntityExt, Transform},
+        },
+        math::aabb::Aabb,
     },
-    math::aabb::Aabb,
 };
 
+// === Rpcs === //
+
+// The maximum distance between a player and the center of a tile it is editing.
+pub const PLAYER_REACH: f32 = 8.;
+
+// The maximum number of tiles which can be edited by a single item use.
+pub const MAX_TILES_PER_USE: usize = 64;
+
+// The longest time step a single input may simulate. Longer frames are simulated as if they took
+// this long on both the client and the server.
+pub const MAX_INPUT_DT: f32 = 0.1;
+
+pub const PLAYER_WALK_SPEED: f32 = 5.;
+pub const PLAYER_JUMP_SPEED: f32 = 10.;
+
+rpc_path! {
+    pub enum PlayerRpcs {
+        UseItem,
+        Input,
+        Motion,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerUseItem {
+    pub slot: u32,
+    pub mode: InteractMode,
+    pub tiles: Vec<(i32, i32)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerUseItemReply {
+    // The authoritative state of every tile whose edit was rejected.
+    pub rejected: Vec<GameSceneSetTile>,
+}
+
+// A single frame of player input. Inputs are numbered sequentially so that the server can tell its
+// owner which of them have been simulated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerInput {
+    pub seq: u32,
+    pub dt: f32,
+    pub walk: i8,
+    pub jump: bool,
+    pub launch: Option<PlayerLaunch>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerLaunch {
+    pub slot: u32,
+    pub target: (f32, f32),
+}
+
+// The authoritative motion state of a player at server time `time` after simulating every input up
+// to and including `last_input`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerMotion {
+    pub time: f64,
+    pub last_input: Option<u32>,
+    pub pos: (f32, f32),
+    pub velocity: (f32, f32),
+}
+
+// === PlayerState === //
+
 #[derive(Debug)]
 pub struct PlayerState {
     transform: Obj<Transform>,
     collider: Obj<Collider>,
     kinematic: Obj<KinematicManager>,
+    inventory: Obj<InventoryData>,
     pub velocity: Vec2,
     pub hotbar_slot: usize,
 }
@@ -24,6 +98,7 @@ impl PlayerState {
             transform: me.obj(),
             collider: me.obj(),
             kinematic: me.deep_obj(),
+            inventory: me.obj(),
             velocity: Vec2::ZERO,
             hotbar_slot: 0,
         }
@@ -51,6 +126,73 @@ impl PlayerState {
         kinematic.has_colliders_in(aabb, filter_descendants(Some(&self.transform)))
     }
 
+    pub fn motion(&self, time: f64, last_input: Option<u32>) -> PlayerMotion {
+        PlayerMotion {
+            time,
+            last_input,
+            pos: self.transform.get().global_pos().into(),
+            velocity: self.velocity.into(),
+        }
+    }
+
+    pub fn set_motion(&mut self, motion: &PlayerMotion) {
+        self.transform.get().set_global_pos(motion.pos.into());
+        self.velocity = motion.velocity.into();
+    }
+
+    // Simulates a single input. The server uses this to move players authoritatively and clients
+    // use it to predict their own movement so the two must never diverge. Inputs which fail to
+    // validate are not applied at all.
+    pub fn apply_input(&mut self, input: &PlayerInput) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            input.dt.is_finite(),
+            "input time step {} is not finite",
+            input.dt
+        );
+
+        let launch = match &input.launch {
+            Some(launch) => Some(self.launch_velocity(launch)?),
+            None => None,
+        };
+
+        if let Some(launch) = launch {
+            self.velocity = launch;
+        }
+
+        let walk = input.walk.clamp(-1, 1) as f32 * PLAYER_WALK_SPEED;
+        self.velocity.x = (self.velocity.x + walk) / 2.;
+
+        if input.jump && self.is_on_ground() {
+            self.velocity.y = -PLAYER_JUMP_SPEED;
+        }
+
+        self.update(input.dt.clamp(0., MAX_INPUT_DT));
+        Ok(())
+    }
+
+    fn launch_velocity(&self, launch: &PlayerLaunch) -> anyhow::Result<Vec2> {
+        let inventory = self.inventory.get();
+        let Some(Some(stack)) = inventory.stacks().get(launch.slot as usize) else {
+            anyhow::bail!("slot {} holds no item", launch.slot);
+        };
+
+        let loaner = ImmutableBorrow::new();
+        let Some(strength) = stack
+            .get()
+            .material
+            .try_get::<LaunchItemDescriptor>(&loaner)
+            .map(|launcher| launcher.strength)
+        else {
+            anyhow::bail!("the item in slot {} cannot launch its user", launch.slot);
+        };
+
+        let target = Vec2::from(launch.target);
+        anyhow::ensure!(target.is_finite(), "launch target {target} is not finite");
+
+        let offset = target - self.transform.get().global_pos();
+        Ok(offset.clamp_length_max(PLAYER_REACH) * strength)
+    }
+
     pub fn update(&mut self, dt: f32) {
         let xform = self.transform.get();
         let aabb = self.collider.get().global_aabb();
@@ -1,2 +1,3 @@
 pub mod actors;
+pub mod scene;
 pub mod services;
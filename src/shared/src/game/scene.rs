@@ -0,0 +1,104 @@
+use aunty::{Entity, Obj, StrongEntity};
+use glam::IVec2;
+
+use crate::{
+    game::actors::inventory::{BlockItemDescriptor, ItemRegistry, LaunchItemDescriptor},
+    util::{
+        game::{
+            actors::ActorManager,
+            kinematic::{KinematicManager, TileColliderDescriptor},
+            tile::{TileLayerConfig, TileMap},
+            transform::{ColliderManager, Transform},
+        },
+        math::aabb::{Aabb, AabbI},
+    },
+};
+
+// === Prefabs === //
+
+// Creates the renderer-free core of a game scene. Both the client and the server build their
+// scenes on top of this.
+pub fn create_game_scene(parent: Option<Obj<Transform>>) -> StrongEntity {
+    StrongEntity::new()
+        .with_debug_label("game scene root")
+        // Attach core services
+        .with_cyclic(Transform::new(parent))
+        .with(ActorManager::default())
+        .with(ColliderManager::default())
+        .with(TileMap::default())
+        .with_cyclic(KinematicManager::new())
+        // Attach game services
+        .with(ItemRegistry::default())
+}
+
+// Registers the scene's materials, tile layers and items. The decorators give each binary a chance
+// to attach its own components to the material and item descriptors before they are registered.
+pub fn populate_game_scene(
+    scene: Entity,
+    mut decorate_material: impl FnMut(&str, StrongEntity) -> StrongEntity,
+    mut decorate_item: impl FnMut(&str, StrongEntity) -> StrongEntity,
+) {
+    // Setup basic map
+    let placeholder = {
+        let mut map = scene.get_mut::<TileMap>();
+        map.push_layer("under_player", TileLayerConfig::from_size(0.5));
+
+        let mut materials = map.materials.get_mut();
+        materials.register(
+            "air",
+            decorate_material("air", StrongEntity::new().with("air descriptor")),
+        );
+        let placeholder = materials.register(
+            "placeholder",
+            decorate_material(
+                "placeholder",
+                StrongEntity::new()
+                    .with("placeholder descriptor")
+                    .with(TileColliderDescriptor::new([Aabb::ZERO_TO_ONE])),
+            ),
+        );
+
+        placeholder
+    };
+
+    // Setup items
+    {
+        let mut item_registry = scene.get_mut::<ItemRegistry>();
+
+        item_registry.register(
+            "stone",
+            decorate_item(
+                "stone",
+                StrongEntity::new()
+                    .with_debug_label("stone")
+                    .with(BlockItemDescriptor {
+                        material: placeholder,
+                    }),
+            ),
+        );
+        item_registry.register(
+            "blaster",
+            decorate_item(
+                "blaster",
+                StrongEntity::new()
+                    .with_debug_label("blaster")
+                    .with(LaunchItemDescriptor { strength: 10. }),
+            ),
+        );
+    }
+}
+
+// Builds the scene's initial map. Only the server does this since clients receive the map through
+// replication.
+pub fn generate_game_map(scene: Entity) {
+    let mut map = scene.get_mut::<TileMap>();
+    let layer = map.layer("under_player");
+    let placeholder = map.materials.get().get_by_name("placeholder");
+
+    for pos in AabbI::new_sized(IVec2::new(-10, 5), IVec2::new(20, 20))
+        .inclusive()
+        .iter()
+    {
+        map.set(layer, pos, placeholder);
+    }
+}
@@ -1,16 +1,70 @@
 use serde::{Deserialize, Serialize};
 
-use crate::rpc_path;
+use crate::{
+    game::actors::player::PlayerRpcs,
+    rpc_path,
+    util::game::rpc::{RpcNodeId, RpcSchemaHasher},
+};
+
+// === Schema === //
+
+pub fn rpc_schema_fingerprint() -> u64 {
+    let mut hasher = RpcSchemaHasher::default();
+    hasher.write_path::<GameSceneRpcs>();
+    hasher.write_path::<PlayerRpcs>();
+    hasher.finish()
+}
+
+// === GameScene === //
 
 rpc_path! {
     pub enum GameSceneRpcs {
         SetTile,
+        LoadChunk,
+        SpawnNode,
+        DespawnNode,
+        SyncClock,
+        ForgetNode,
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameSceneSetTile {
+    pub layer: u32,
     pub x: i32,
     pub y: i32,
-    pub state: i32,
+    pub material: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameSceneTileChunk {
+    pub layer: u32,
+    pub x: i32,
+    pub y: i32,
+    pub data: Vec<u16>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GameSceneSpawnNode {
+    pub id: RpcNodeId,
+    pub kind: String,
+    pub owned: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GameSceneDespawnNode {
+    pub id: RpcNodeId,
+}
+
+// Sent when a node leaves a peer's area of interest. The peer should get rid of its copy of the node
+// as if it had been despawned. The node may be spawned for the peer again later.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GameSceneForgetNode {
+    pub id: RpcNodeId,
+}
+
+// The server's reply to a clock sync request, which carries no payload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameSceneClockSample {
+    pub server_time: f64,
 }
@@ -96,7 +96,7 @@ delegate! {
 }
 
 delegate! {
-    pub fn UpdateHandler()
+    pub fn UpdateHandler(dt: f32)
 }
 
 // === DespawnStep === //
@@ -1,5 +1,6 @@
 pub mod actors;
 pub mod kinematic;
 pub mod rpc;
+pub mod sync;
 pub mod tile;
 pub mod transform;
@@ -1,9 +1,18 @@
-use std::{fmt, hash, marker::PhantomData, num::NonZeroU64};
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt, hash,
+    marker::PhantomData,
+    mem,
+    num::NonZeroU64,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use aunty::{delegate, make_extensible, CyclicCtor, Entity, Obj};
 use bytes::Bytes;
 use derive_where::derive_where;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use super::{actors::DespawnStep, transform::EntityExt};
@@ -16,6 +25,8 @@ pub trait CompleteRpcPath: fmt::Debug {
     const MAX: u32;
 
     fn as_index(&self) -> u32;
+
+    fn hash_schema(hasher: &mut RpcSchemaHasher);
 }
 
 impl CompleteRpcPath for () {
@@ -24,6 +35,10 @@ impl CompleteRpcPath for () {
     fn as_index(&self) -> u32 {
         0
     }
+
+    fn hash_schema(_hasher: &mut RpcSchemaHasher) {
+        // (the unit path has no structure to hash)
+    }
 }
 
 pub trait RpcPath<R = ()>: Copy {
@@ -85,8 +100,8 @@ impl<R: CompleteRpcPath> RpcPath<R> for EmptyPathBuilder {
 #[doc(hidden)]
 pub mod rpc_path_macro_internals {
     pub use {
-        super::CompleteRpcPath,
-        std::{primitive::u32, unreachable},
+        super::{CompleteRpcPath, RpcSchemaHasher},
+        std::{primitive::u32, stringify, unreachable},
     };
 }
 
@@ -125,27 +140,107 @@ macro_rules! rpc_path {
 
                 $crate::util::game::rpc::rpc_path_macro_internals::unreachable!();
             }
+
+            fn hash_schema(hasher: &mut $crate::util::game::rpc::rpc_path_macro_internals::RpcSchemaHasher) {
+                hasher.write_str($crate::util::game::rpc::rpc_path_macro_internals::stringify!($enum_name));
+
+                $(
+                    hasher.write_str($crate::util::game::rpc::rpc_path_macro_internals::stringify!($variant_name));
+                    hasher.write_path::<($($variant_ty)?)>();
+                )*
+            }
         }
     )*};
 }
 
 pub use rpc_path;
 
+// Schema
+// We can't use the standard library's hashers here because their output is not guaranteed to be
+// stable across platforms or compiler versions and both peers must agree on the fingerprint. This
+// is just FNV-1a.
+#[derive(Debug, Clone)]
+pub struct RpcSchemaHasher(u64);
+
+impl Default for RpcSchemaHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl RpcSchemaHasher {
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write_u32(value.len() as u32);
+        self.write_bytes(value.as_bytes());
+    }
+
+    pub fn write_path<P: CompleteRpcPath>(&mut self) {
+        self.write_u32(P::MAX);
+        P::hash_schema(self);
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
 // === Protocol === //
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct RpcPacket {
     pub catchup: Vec<RpcPacketMessage>,
     pub messages: Vec<RpcPacketMessage>,
+    pub replies: Vec<RpcPacketReply>,
+
+    // Messages sent over the unreliable channels. These are only ever produced in packets meant
+    // for `RpcDelivery::Unreliable` and are stamped with that packet's `sequence`.
+    pub unreliable: Vec<RpcPacketMessage>,
+    pub sequenced: Vec<RpcPacketMessage>,
+    pub sequence: u32,
+}
+
+// How a packet produced by `RpcManager::drain_queues` must be sent to its peer.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum RpcDelivery {
+    // The packet must arrive exactly once and in order with respect to other reliable packets.
+    Reliable,
+
+    // The packet may be dropped, duplicated, or reordered.
+    Unreliable,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RpcPacketMessage {
     pub node_id: u64,
     pub path: u32,
+
+    // Set for requests so that the receiver can reply to them, even if it rejects them before they
+    // reach their handler.
+    pub call_id: Option<u64>,
     pub data: Bytes,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcPacketReply {
+    pub call_id: u64,
+    pub result: Result<Bytes, String>,
+}
+
+// The reason given to callers whose request was rejected. The actual reason is only reported locally
+// since it may describe our internals.
+const REJECTED_REQUEST_REPLY: &str = "the request was rejected";
+
 pub fn encode_packet(v: &impl Serialize) -> Bytes {
     Bytes::from(bincode::serialize(v).unwrap())
 }
@@ -156,7 +251,7 @@ pub fn decode_packet<'a, P: Deserialize<'a>>(v: &'a Bytes) -> anyhow::Result<P>
 
 // === RpcNodeId === //
 
-#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct RpcNodeId(pub NonZeroU64);
 
 impl RpcNodeId {
@@ -166,6 +261,51 @@ impl RpcNodeId {
     });
 }
 
+// === NetEntity === //
+
+// A reference to a replicated node which can be embedded in RPC payloads. It is encoded as the
+// node's `RpcNodeId` and has to be resolved against the receiving `RpcManager` before use. Peers
+// routinely mention nodes which the receiver doesn't know about (e.g. nodes outside of its area of
+// interest or nodes which were just despawned) so failing to resolve a reference is reported as
+// `RpcError::UnknownReference` rather than as a decode error.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NetEntity(pub RpcNodeId);
+
+impl NetEntity {
+    pub fn new<M: RpcNetMode>(node: &RpcNode<M>) -> Self {
+        Self(node.id)
+    }
+
+    pub fn id(self) -> RpcNodeId {
+        self.0
+    }
+
+    pub fn resolve<M: RpcNetMode>(
+        self,
+        manager: &RpcManager<M>,
+    ) -> Result<Obj<RpcNode<M>>, RpcUnknownReference> {
+        manager
+            .nodes
+            .get(&self.0)
+            .cloned()
+            .ok_or(RpcUnknownReference(self.0))
+    }
+}
+
+// Returned by `NetEntity::resolve`. Handlers which propagate it get it reported as
+// `RpcError::UnknownReference`.
+#[derive(Debug, Copy, Clone)]
+pub struct RpcUnknownReference(pub RpcNodeId);
+
+impl fmt::Display for RpcUnknownReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "payload references unknown node {:?}", self.0)
+    }
+}
+
+impl std::error::Error for RpcUnknownReference {}
+
 // === NetMode === //
 
 mod sealed {
@@ -203,25 +343,40 @@ pub trait RpcNetMode: NetMode {
     fn import_catchup_packets(
         state: &mut Self::ManagerCatchupState,
         packets: &[RpcPacketMessage],
-    ) -> anyhow::Result<()>;
+    ) -> Result<(), RpcError>;
 
     fn clear_catchup_packets(state: &mut Self::ManagerCatchupState);
 
     fn produce_catchup_packets(state: Self::QueueCatchupState) -> Vec<RpcPacketMessage>;
+
+    fn forget_peer(state: &mut Self::NodeCatchupState, peer: Self::Peer);
+
+    fn is_authorized(
+        authority: RpcAuthority,
+        owner: Option<Self::Peer>,
+        state: &Self::NodeCatchupState,
+        peer: Self::Peer,
+    ) -> bool;
+}
+
+#[derive(Debug, Default)]
+pub struct ServerNodeCatchupState {
+    generators: Vec<(u32, RpcCatchupGenerator)>,
+    observers: FxHashSet<Entity>,
 }
 
 impl RpcNetMode for ServerNetMode {
     type Peer = Entity;
     type QueueCatchupState = FxHashMap<RpcNodeId, Vec<(u32, Bytes)>>;
     type ManagerCatchupState = ();
-    type NodeCatchupState = Vec<(u32, RpcCatchupGenerator)>;
+    type NodeCatchupState = ServerNodeCatchupState;
 
     fn import_catchup_packets(
         _state: &mut Self::ManagerCatchupState,
         packets: &[RpcPacketMessage],
-    ) -> anyhow::Result<()> {
+    ) -> Result<(), RpcError> {
         if !packets.is_empty() {
-            anyhow::bail!("peer somehow sent a catchup packet to the server");
+            return Err(RpcError::UnexpectedCatchup);
         }
 
         Ok(())
@@ -240,11 +395,30 @@ impl RpcNetMode for ServerNetMode {
                     .map(move |(path, data)| RpcPacketMessage {
                         node_id: node_id.0.get(),
                         path,
+                        call_id: None,
                         data,
                     })
             })
             .collect()
     }
+
+    fn forget_peer(state: &mut Self::NodeCatchupState, peer: Self::Peer) {
+        state.observers.remove(&peer);
+    }
+
+    fn is_authorized(
+        authority: RpcAuthority,
+        owner: Option<Self::Peer>,
+        state: &Self::NodeCatchupState,
+        peer: Self::Peer,
+    ) -> bool {
+        match authority {
+            // Peers can only know about the nodes they were caught up on.
+            RpcAuthority::AnyPeer => owner == Some(peer) || state.observers.contains(&peer),
+            RpcAuthority::OwnerOnly => owner == Some(peer),
+            RpcAuthority::ServerOnly => false,
+        }
+    }
 }
 
 impl RpcNetMode for ClientNetMode {
@@ -256,10 +430,10 @@ impl RpcNetMode for ClientNetMode {
     fn import_catchup_packets(
         state: &mut Self::ManagerCatchupState,
         packets: &[RpcPacketMessage],
-    ) -> anyhow::Result<()> {
+    ) -> Result<(), RpcError> {
         for packet in packets {
             let Some(node_id) = NonZeroU64::new(packet.node_id).map(RpcNodeId) else {
-                anyhow::bail!("encountered a catchup packet with a target node ID of 0");
+                return Err(RpcError::NullNodeId);
             };
             state.insert((node_id, packet.path), packet.data.clone());
         }
@@ -278,62 +452,481 @@ impl RpcNetMode for ClientNetMode {
     fn produce_catchup_packets(_state: Self::QueueCatchupState) -> Vec<RpcPacketMessage> {
         vec![]
     }
+
+    fn forget_peer(_state: &mut Self::NodeCatchupState, _peer: Self::Peer) {
+        // (clients don't track observers)
+    }
+
+    fn is_authorized(
+        _authority: RpcAuthority,
+        _owner: Option<Self::Peer>,
+        _state: &Self::NodeCatchupState,
+        _peer: Self::Peer,
+    ) -> bool {
+        // Our only peer is the server, which has authority over everything.
+        true
+    }
+}
+
+// Errors
+#[derive(Debug)]
+pub enum RpcError {
+    NullNodeId,
+    UnknownNode {
+        node_id: RpcNodeId,
+    },
+    UnknownPath {
+        node_id: RpcNodeId,
+        path: u32,
+    },
+    Decode {
+        node_id: RpcNodeId,
+        path: u32,
+        error: anyhow::Error,
+    },
+    Handler {
+        node_id: RpcNodeId,
+        path: u32,
+        error: anyhow::Error,
+    },
+    UnknownReference {
+        node_id: RpcNodeId,
+        path: u32,
+        referenced: RpcNodeId,
+    },
+    UnexpectedCatchup,
+    UnknownCall {
+        call_id: u64,
+    },
+    Unauthorized {
+        node_id: RpcNodeId,
+        path: u32,
+    },
+}
+
+impl RpcError {
+    pub fn node_id(&self) -> Option<RpcNodeId> {
+        match self {
+            RpcError::UnknownNode { node_id }
+            | RpcError::UnknownPath { node_id, .. }
+            | RpcError::Decode { node_id, .. }
+            | RpcError::Handler { node_id, .. }
+            | RpcError::UnknownReference { node_id, .. }
+            | RpcError::Unauthorized { node_id, .. } => Some(*node_id),
+            RpcError::NullNodeId | RpcError::UnexpectedCatchup | RpcError::UnknownCall { .. } => {
+                None
+            }
+        }
+    }
+
+    pub fn path(&self) -> Option<u32> {
+        match self {
+            RpcError::UnknownPath { path, .. }
+            | RpcError::Decode { path, .. }
+            | RpcError::Handler { path, .. }
+            | RpcError::UnknownReference { path, .. }
+            | RpcError::Unauthorized { path, .. } => Some(*path),
+            RpcError::NullNodeId
+            | RpcError::UnknownNode { .. }
+            | RpcError::UnexpectedCatchup
+            | RpcError::UnknownCall { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcError::NullNodeId => f.write_str("encountered invalid null node ID"),
+            RpcError::UnknownNode { node_id } => {
+                write!(f, "attempted to send RPC to unknown node {node_id:?}")
+            }
+            RpcError::UnknownPath { node_id, path } => write!(
+                f,
+                "attempted to send RPC to unknown path {path:?} on node with id {node_id:?}"
+            ),
+            RpcError::Decode {
+                node_id,
+                path,
+                error,
+            } => write!(
+                f,
+                "failed to decode RPC for path {path:?} on node with id {node_id:?}: {error}"
+            ),
+            RpcError::Handler {
+                node_id,
+                path,
+                error,
+            } => write!(
+                f,
+                "RPC handler for path {path:?} on node with id {node_id:?} failed: {error}"
+            ),
+            RpcError::UnknownReference {
+                node_id,
+                path,
+                referenced,
+            } => write!(
+                f,
+                "RPC for path {path:?} on node with id {node_id:?} references unknown node {referenced:?}"
+            ),
+            RpcError::UnexpectedCatchup => {
+                f.write_str("peer somehow sent a catchup packet to the server")
+            }
+            RpcError::UnknownCall { call_id } => {
+                write!(f, "received a reply to unknown call {call_id}")
+            }
+            RpcError::Unauthorized { node_id, path } => write!(
+                f,
+                "peer is not authorized to send RPCs to path {path:?} on node with id {node_id:?}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+// Wraps errors produced while decoding a message's payload so that `process_packet` can report them
+// as `RpcError::Decode` rather than as handler failures.
+#[derive(Debug)]
+struct RpcDecodeError(anyhow::Error);
+
+impl fmt::Display for RpcDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl std::error::Error for RpcDecodeError {}
+
+fn decode_message<'a, P: Deserialize<'a>>(v: &'a Bytes) -> anyhow::Result<P> {
+    decode_packet(v).map_err(|err| anyhow::Error::new(RpcDecodeError(err)))
+}
+
+#[derive(Debug)]
+pub enum RpcCallError {
+    Remote(String),
+    Decode(anyhow::Error),
+    Timeout,
+    Disconnected,
+}
+
+impl fmt::Display for RpcCallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcCallError::Remote(reason) => write!(f, "remote handler failed: {reason}"),
+            RpcCallError::Decode(err) => write!(f, "failed to decode reply: {err}"),
+            RpcCallError::Timeout => f.write_str("call timed out"),
+            RpcCallError::Disconnected => f.write_str("peer disconnected before replying"),
+        }
+    }
+}
+
+impl std::error::Error for RpcCallError {}
+
+// Authority
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum RpcAuthority {
+    // Any peer which knows about the node may send to this path. On the server, this means the
+    // node's owner and the peers which have been caught up on it.
+    AnyPeer,
+
+    // Only the node's owner may send to this path.
+    OwnerOnly,
+
+    // Only the server may send to this path. Use this for client-side bindings.
+    ServerOnly,
+}
+
+// Channels
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Default)]
+pub enum RpcChannel {
+    // Messages arrive exactly once and in the order in which they were sent, relative to every
+    // other reliable message. A lost packet delays everything queued behind it.
+    #[default]
+    ReliableOrdered,
+
+    // Messages may be dropped, duplicated, or arrive out of order.
+    Unreliable,
+
+    // Messages may be dropped but are never delivered twice or after a newer message sent to the
+    // same path of the same node. Use this for state which is re-sent in full whenever it changes.
+    UnreliableSequenced,
 }
 
 // Core
 delegate! {
-    pub fn RpcMessageHandler<P>(peer: P, node: Entity, data: &Bytes) -> anyhow::Result<()>
+    pub fn RpcMessageHandler<P>(
+        peer: P,
+        node: Entity,
+        call_id: Option<u64>,
+        data: &Bytes,
+    ) -> anyhow::Result<()>
 }
 
 delegate! {
     pub fn RpcCatchupGenerator(peer: Entity, node: Entity) -> Bytes
 }
 
+delegate! {
+    pub fn RpcFlushHandler()
+}
+
 #[derive_where(Debug, Default)]
 pub struct RpcManager<M: RpcNetMode> {
     _ty: PhantomData<M>,
     nodes: FxHashMap<RpcNodeId, Obj<RpcNode<M>>>,
     packet_queues: FxHashMap<M::Peer, PeerPacketQueue<M>>,
     catchup_state: M::ManagerCatchupState,
+    id_gen: u64,
+    call_id_gen: u64,
+    pending_calls: FxHashMap<u64, PendingCall<M>>,
+
+    // Messages for nodes which haven't been spawned yet. These are moved to `ready_deferred` once
+    // their node registers itself.
+    deferral_window: Option<Duration>,
+    deferred: FxHashMap<RpcNodeId, Vec<DeferredMessage<M>>>,
+    ready_deferred: VecDeque<(RpcNodeId, DeferredMessage<M>)>,
+
+    // Handlers to run right before the queues are next drained, along with the node whose despawn
+    // cancels them.
+    flush_handlers: Vec<(Option<RpcNodeId>, RpcFlushHandler)>,
+
+    // The sequence number of the last unreliable packet we sent to each peer and the sequence
+    // number of the newest packet which delivered a sequenced message to each of our paths.
+    sent_sequences: FxHashMap<M::Peer, u32>,
+    received_sequences: FxHashMap<(M::Peer, RpcNodeId, u32), u32>,
+}
+
+#[derive_where(Debug)]
+struct DeferredMessage<M: RpcNetMode> {
+    peer: M::Peer,
+    path: u32,
+    call_id: Option<u64>,
+    data: Bytes,
+    deadline: Instant,
 }
 
 #[derive_where(Debug, Default)]
 struct PeerPacketQueue<M: RpcNetMode> {
     messages: Vec<RpcPacketMessage>,
+    replies: Vec<RpcPacketReply>,
     catchups: M::QueueCatchupState,
+    unreliable: Vec<RpcPacketMessage>,
+    sequenced: Vec<RpcPacketMessage>,
 }
 
+#[derive_where(Debug)]
+struct PendingCall<M: RpcNetMode> {
+    peer: M::Peer,
+    deadline: Instant,
+    state: RpcCallState,
+}
+
+type RpcCallState = Rc<RefCell<Option<Result<Bytes, RpcCallError>>>>;
+
 make_extensible!(pub RpcManagerObj<M> for RpcManager where M: RpcNetMode);
 
 impl<M: RpcNetMode> RpcManager<M> {
+    // Enables buffering of messages addressed to nodes which don't exist yet. These are replayed in
+    // order once the node is created or reported as unknown once `window` has elapsed.
+    pub fn with_deferral_window(mut self, window: Duration) -> Self {
+        self.deferral_window = Some(window);
+        self
+    }
+
     fn packet_queue(&mut self, peer: M::Peer) -> &mut PeerPacketQueue<M> {
         self.packet_queues.entry(peer).or_default()
     }
 
     pub fn queue_message(&mut self, peer: M::Peer, node: RpcNodeId, path: u32, data: Bytes) {
+        self.queue_message_on(RpcChannel::ReliableOrdered, peer, node, path, data);
+    }
+
+    pub fn queue_message_on(
+        &mut self,
+        channel: RpcChannel,
+        peer: M::Peer,
+        node: RpcNodeId,
+        path: u32,
+        data: Bytes,
+    ) {
+        let queue = self.packet_queue(peer);
+        let message = RpcPacketMessage {
+            node_id: node.0.get(),
+            path,
+            call_id: None,
+            data,
+        };
+
+        match channel {
+            RpcChannel::ReliableOrdered => queue.messages.push(message),
+            RpcChannel::Unreliable => queue.unreliable.push(message),
+            RpcChannel::UnreliableSequenced => {
+                // Receivers only accept one message per path from each packet so older messages
+                // are superseded rather than sent alongside the new one.
+                if let Some(queued) = queue
+                    .sequenced
+                    .iter_mut()
+                    .find(|queued| queued.node_id == message.node_id && queued.path == path)
+                {
+                    *queued = message;
+                } else {
+                    queue.sequenced.push(message);
+                }
+            }
+        }
+    }
+
+    fn queue_request(
+        &mut self,
+        peer: M::Peer,
+        node: RpcNodeId,
+        path: u32,
+        call_id: u64,
+        data: Bytes,
+    ) {
         self.packet_queue(peer).messages.push(RpcPacketMessage {
             node_id: node.0.get(),
             path,
+            call_id: Some(call_id),
             data,
         });
     }
 
-    pub fn drain_queues(&mut self) -> impl Iterator<Item = (M::Peer, RpcPacket)> + '_ {
-        self.packet_queues.drain().map(|(peer, queue)| {
-            (
+    pub fn queue_flush(&mut self, handler: RpcFlushHandler) {
+        self.flush_handlers.push((None, handler));
+    }
+
+    // Like `queue_flush` but the handler is dropped without running if `node` despawns first.
+    pub fn queue_node_flush(&mut self, node: RpcNodeId, handler: RpcFlushHandler) {
+        self.flush_handlers.push((Some(node), handler));
+    }
+
+    pub fn queue_reply(&mut self, peer: M::Peer, call_id: u64, result: Result<Bytes, String>) {
+        self.packet_queue(peer)
+            .replies
+            .push(RpcPacketReply { call_id, result });
+    }
+
+    fn begin_call(&mut self, peer: M::Peer, timeout: Duration) -> (u64, RpcCallState) {
+        self.call_id_gen += 1;
+        let call_id = self.call_id_gen;
+        let state = RpcCallState::default();
+
+        self.pending_calls.insert(
+            call_id,
+            PendingCall {
                 peer,
-                RpcPacket {
-                    messages: queue.messages,
-                    catchup: M::produce_catchup_packets(queue.catchups),
-                },
-            )
+                deadline: Instant::now() + timeout,
+                state: state.clone(),
+            },
+        );
+
+        (call_id, state)
+    }
+
+    pub fn process_timeouts(&mut self) {
+        let now = Instant::now();
+
+        self.pending_calls.retain(|_, call| {
+            if call.deadline > now {
+                return true;
+            }
+
+            *call.state.borrow_mut() = Some(Err(RpcCallError::Timeout));
+            false
+        });
+    }
+
+    pub fn remove_peer(&mut self, peer: M::Peer) {
+        self.packet_queues.remove(&peer);
+        self.sent_sequences.remove(&peer);
+        self.received_sequences
+            .retain(|&(sender, _, _), _| sender != peer);
+
+        for node in self.nodes.values() {
+            M::forget_peer(&mut node.get_mut().catchup_state, peer);
+        }
+
+        self.pending_calls.retain(|_, call| {
+            if call.peer != peer {
+                return true;
+            }
+
+            *call.state.borrow_mut() = Some(Err(RpcCallError::Disconnected));
+            false
+        });
+
+        self.deferred.retain(|_, messages| {
+            messages.retain(|message| message.peer != peer);
+            !messages.is_empty()
+        });
+        self.ready_deferred
+            .retain(|(_, message)| message.peer != peer);
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &Obj<RpcNode<M>>> + '_ {
+        self.nodes.values()
+    }
+
+    // Produces up to one packet of each delivery kind for every peer with queued messages.
+    pub fn drain_queues(&mut self) -> impl Iterator<Item = (M::Peer, RpcDelivery, RpcPacket)> + '_ {
+        let sent_sequences = &mut self.sent_sequences;
+
+        self.packet_queues.drain().flat_map(move |(peer, queue)| {
+            let reliable = RpcPacket {
+                catchup: M::produce_catchup_packets(queue.catchups),
+                messages: queue.messages,
+                replies: queue.replies,
+                ..Default::default()
+            };
+
+            let reliable = (!reliable.catchup.is_empty()
+                || !reliable.messages.is_empty()
+                || !reliable.replies.is_empty())
+            .then_some((peer, RpcDelivery::Reliable, reliable));
+
+            let unreliable =
+                (!queue.unreliable.is_empty() || !queue.sequenced.is_empty()).then(|| {
+                    let sequence = sent_sequences.entry(peer).or_default();
+                    *sequence += 1;
+
+                    let packet = RpcPacket {
+                        unreliable: queue.unreliable,
+                        sequenced: queue.sequenced,
+                        sequence: *sequence,
+                        ..Default::default()
+                    };
+
+                    (peer, RpcDelivery::Unreliable, packet)
+                });
+
+            reliable.into_iter().chain(unreliable)
         })
     }
 }
 
+impl RpcManager<ServerNetMode> {
+    pub fn allocate_id(&mut self) -> RpcNodeId {
+        // IDs up to and including `RpcNodeId::ROOT` are reserved for statically-known nodes.
+        self.id_gen = self.id_gen.max(RpcNodeId::ROOT.0.get()) + 1;
+        RpcNodeId(NonZeroU64::new(self.id_gen).unwrap())
+    }
+}
+
 impl<M: RpcNetMode> RpcManagerObj<M> {
+    // Runs the handlers registered with `queue_flush` since the last call. Their messages go out
+    // with the next `drain_queues`.
+    pub fn run_flushes(&self) {
+        let handlers = mem::take(&mut self.obj.get_mut().flush_handlers);
+
+        for (_, handler) in handlers {
+            handler.call();
+        }
+    }
+
     #[must_use]
-    pub fn process_packet(&self, peer: M::Peer, packet: &RpcPacket) -> Vec<anyhow::Error> {
+    pub fn process_packet(&self, peer: M::Peer, packet: &RpcPacket) -> Vec<RpcError> {
         let mut errors = Vec::new();
 
         // Process catchup packets
@@ -343,38 +936,83 @@ impl<M: RpcNetMode> RpcManagerObj<M> {
             return vec![err];
         }
 
-        // Process message packets
-        for part in &packet.messages {
-            let Some(id) = NonZeroU64::new(part.node_id).map(RpcNodeId) else {
-                errors.push(anyhow::anyhow!("encountered invalid null node ID"));
-                continue;
+        // Process replies to our calls
+        for reply in &packet.replies {
+            let call = {
+                let mut manager = self.obj.get_mut();
+
+                // Peers may only reply to calls which were made to them.
+                let is_valid = manager
+                    .pending_calls
+                    .get(&reply.call_id)
+                    .is_some_and(|call| call.peer == peer);
+
+                if is_valid {
+                    manager.pending_calls.remove(&reply.call_id)
+                } else {
+                    None
+                }
             };
 
-            let Some(target) = self.obj.get().nodes.get(&id).cloned() else {
-                errors.push(anyhow::anyhow!(
-                    "attempted to send RPC to unknown node {id:?}"
-                ));
+            let Some(call) = call else {
+                errors.push(RpcError::UnknownCall {
+                    call_id: reply.call_id,
+                });
                 continue;
             };
 
-            let Some(handler) = target
-                .get()
-                .message_handlers
-                .get(part.path as usize)
-                .cloned()
-                .flatten()
-            else {
-                errors.push(anyhow::anyhow!(
-                    "attempted to send RPC to unknown path {:?} on node {:?} with id {id:?}",
-                    part.path,
-                    target,
-                ));
-                continue;
-            };
+            *call.state.borrow_mut() = Some(reply.result.clone().map_err(RpcCallError::Remote));
+        }
+
+        // Expire this peer's deferred messages
+        {
+            let now = Instant::now();
+            let mut expired = Vec::new();
+
+            self.obj.get_mut().deferred.retain(|&node_id, messages| {
+                messages.retain(|message| {
+                    if message.peer != peer || message.deadline > now {
+                        return true;
+                    }
+
+                    expired.push((node_id, message.call_id));
+                    false
+                });
+                !messages.is_empty()
+            });
+
+            for (node_id, call_id) in expired {
+                errors.push(self.reject(peer, call_id, RpcError::UnknownNode { node_id }));
+            }
+        }
+
+        // Process message packets
+        self.replay_deferred(&mut errors);
+
+        for part in packet.messages.iter().chain(&packet.unreliable) {
+            self.process_message(peer, part, &mut errors);
+        }
 
-            if let Err(err) = handler.call(peer, target.get().me, &part.data) {
-                errors.push(err);
+        for part in &packet.sequenced {
+            // Drop messages which were overtaken by a newer packet or which were already delivered
+            // by a duplicate of this one. Senders never put two messages for the same path in a
+            // single packet.
+            if let Some(id) = NonZeroU64::new(part.node_id).map(RpcNodeId) {
+                let mut manager = self.obj.get_mut();
+                let key = (peer, id, part.path);
+
+                if manager
+                    .received_sequences
+                    .get(&key)
+                    .is_some_and(|&latest| latest >= packet.sequence)
+                {
+                    continue;
+                }
+
+                manager.received_sequences.insert(key, packet.sequence);
             }
+
+            self.process_message(peer, part, &mut errors);
         }
 
         // Clear catchup packets
@@ -383,6 +1021,135 @@ impl<M: RpcNetMode> RpcManagerObj<M> {
         // Report errors
         errors
     }
+
+    fn process_message(&self, peer: M::Peer, part: &RpcPacketMessage, errors: &mut Vec<RpcError>) {
+        let Some(id) = NonZeroU64::new(part.node_id).map(RpcNodeId) else {
+            errors.push(RpcError::NullNodeId);
+            return;
+        };
+
+        // Defer messages to unknown nodes if requested.
+        {
+            let mut manager = self.obj.get_mut();
+
+            if let Some(window) = manager.deferral_window {
+                if !manager.nodes.contains_key(&id) {
+                    manager
+                        .deferred
+                        .entry(id)
+                        .or_default()
+                        .push(DeferredMessage {
+                            peer,
+                            path: part.path,
+                            call_id: part.call_id,
+                            data: part.data.clone(),
+                            deadline: Instant::now() + window,
+                        });
+                    return;
+                }
+            }
+        }
+
+        if let Err(error) = self.dispatch(peer, id, part.path, part.call_id, &part.data) {
+            errors.push(error);
+        }
+
+        // The handler may have spawned nodes with deferred messages. These were sent before the
+        // remaining messages in this packet so they must be delivered first.
+        self.replay_deferred(errors);
+    }
+
+    fn replay_deferred(&self, errors: &mut Vec<RpcError>) {
+        loop {
+            let Some((id, message)) = self.obj.get_mut().ready_deferred.pop_front() else {
+                break;
+            };
+
+            let result = self.dispatch(
+                message.peer,
+                id,
+                message.path,
+                message.call_id,
+                &message.data,
+            );
+
+            if let Err(error) = result {
+                errors.push(error);
+            }
+        }
+    }
+
+    fn dispatch(
+        &self,
+        peer: M::Peer,
+        id: RpcNodeId,
+        path: u32,
+        call_id: Option<u64>,
+        data: &Bytes,
+    ) -> Result<(), RpcError> {
+        let Some(target) = self.obj.get().nodes.get(&id).cloned() else {
+            return Err(self.reject(peer, call_id, RpcError::UnknownNode { node_id: id }));
+        };
+
+        let Some((authority, handler)) = target
+            .get()
+            .message_handlers
+            .get(path as usize)
+            .cloned()
+            .flatten()
+        else {
+            return Err(self.reject(peer, call_id, RpcError::UnknownPath { node_id: id, path }));
+        };
+
+        let is_authorized = {
+            let target = target.get();
+            M::is_authorized(authority, target.owner, &target.catchup_state, peer)
+        };
+
+        if !is_authorized {
+            return Err(self.reject(peer, call_id, RpcError::Unauthorized { node_id: id, path }));
+        }
+
+        handler
+            .call(peer, target.get().me, call_id, data)
+            .map_err(|error| {
+                let error = match error.downcast::<RpcDecodeError>() {
+                    Ok(RpcDecodeError(error)) => {
+                        return RpcError::Decode {
+                            node_id: id,
+                            path,
+                            error,
+                        }
+                    }
+                    Err(error) => error,
+                };
+
+                match error.downcast::<RpcUnknownReference>() {
+                    Ok(RpcUnknownReference(referenced)) => RpcError::UnknownReference {
+                        node_id: id,
+                        path,
+                        referenced,
+                    },
+                    Err(error) => RpcError::Handler {
+                        node_id: id,
+                        path,
+                        error,
+                    },
+                }
+            })
+    }
+
+    // Requests which never reach their handler are rejected right away so that their callers don't
+    // have to wait for them to time out.
+    fn reject(&self, peer: M::Peer, call_id: Option<u64>, error: RpcError) -> RpcError {
+        if let Some(call_id) = call_id {
+            self.obj
+                .get_mut()
+                .queue_reply(peer, call_id, Err(REJECTED_REQUEST_REPLY.to_string()));
+        }
+
+        error
+    }
 }
 
 // === RpcNode === //
@@ -400,9 +1167,10 @@ pub struct RpcNode<M: RpcNetMode> {
     manager: Obj<RpcManager<M>>,
     id: RpcNodeId,
     me: Entity,
+    owner: Option<M::Peer>,
 
     // Handlers
-    message_handlers: Vec<Option<RpcMessageHandler<M::Peer>>>,
+    message_handlers: Vec<Option<(RpcAuthority, RpcMessageHandler<M::Peer>)>>,
     catchup_state: M::NodeCatchupState,
 }
 
@@ -410,15 +1178,35 @@ make_extensible!(pub RpcNodeObj<M> for RpcNode where M: RpcNetMode);
 
 impl<M: RpcNetMode> RpcNode<M> {
     pub fn new(id: RpcNodeId) -> impl CyclicCtor<Self> {
+        Self::new_inner(id, None)
+    }
+
+    pub fn new_owned(id: RpcNodeId, owner: M::Peer) -> impl CyclicCtor<Self> {
+        Self::new_inner(id, Some(owner))
+    }
+
+    fn new_inner(id: RpcNodeId, owner: Option<M::Peer>) -> impl CyclicCtor<Self> {
         move |me, ob| {
             let manager = me.deep_obj::<RpcManager<M>>();
 
-            manager.get_mut().nodes.insert(id, ob.clone());
+            {
+                let mut manager = manager.get_mut();
+                manager.nodes.insert(id, ob.clone());
+
+                // Messages which arrived before we were spawned are replayed once the current
+                // packet's handler returns since our handlers haven't been bound yet.
+                if let Some(messages) = manager.deferred.remove(&id) {
+                    manager
+                        .ready_deferred
+                        .extend(messages.into_iter().map(|message| (id, message)));
+                }
+            }
 
             Self {
                 despawn: DespawnStep::default(),
                 me,
                 id,
+                owner,
                 manager,
                 message_handlers: Vec::new(),
                 catchup_state: <M::NodeCatchupState>::default(),
@@ -434,13 +1222,42 @@ impl<M: RpcNetMode> RpcNode<M> {
         &self.manager
     }
 
+    // Resolves a reference received in one of this node's payloads against our manager.
+    pub fn resolve(&self, entity: NetEntity) -> Result<Obj<RpcNode<M>>, RpcUnknownReference> {
+        entity.resolve(&self.manager.get())
+    }
+
     pub fn entity(&self) -> Entity {
         self.me
     }
 
+    pub fn owner(&self) -> Option<M::Peer> {
+        self.owner
+    }
+
+    pub fn set_owner(&mut self, owner: Option<M::Peer>) {
+        self.owner = owner;
+    }
+
     pub fn despawn(&self) {
         self.despawn.mark();
-        self.manager.get_mut().nodes.remove(&self.id);
+
+        // Nodes can be forgotten and re-announced before their old entity finishes despawning so
+        // we have to make sure that the ID still belongs to us.
+        let mut manager = self.manager.get_mut();
+        if manager
+            .nodes
+            .get(&self.id)
+            .is_some_and(|node| node.get().me == self.me)
+        {
+            manager.nodes.remove(&self.id);
+            manager
+                .received_sequences
+                .retain(|&(_, node, _), _| node != self.id);
+            manager
+                .flush_handlers
+                .retain(|&(node, _)| node != Some(self.id));
+        }
     }
 }
 
@@ -449,24 +1266,35 @@ impl<M: RpcNetMode> RpcNodeObj<M> {
         RpcNodeBuilder {
             node: &self.obj,
             path: EmptyPathBuilder,
+            channel: RpcChannel::default(),
         }
     }
 }
 
+impl RpcNode<ServerNetMode> {
+    pub fn observers(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.catchup_state.observers.iter().copied()
+    }
+
+    pub fn is_observed_by(&self, peer: Entity) -> bool {
+        self.catchup_state.observers.contains(&peer)
+    }
+
+    // Makes the node forget that it has caught up `peer`, returning whether it had. The next call
+    // to `queue_catchup` for that peer will catch it up again.
+    pub fn forget_peer(&mut self, peer: Entity) -> bool {
+        self.catchup_state.observers.remove(&peer)
+    }
+}
+
 impl RpcNodeObj<ServerNetMode> {
     pub fn queue_catchup(&self, peer: Entity) {
         let (me, id, handlers, manager) = {
-            let me = self.obj.get();
+            let mut me = self.obj.get_mut();
             let id = me.id;
 
             // Check if we have already caught up this peer.
-            if me
-                .manager
-                .get_mut()
-                .packet_queue(peer)
-                .catchups
-                .contains_key(&id)
-            {
+            if !me.catchup_state.observers.insert(peer) {
                 return;
             }
 
@@ -474,7 +1302,7 @@ impl RpcNodeObj<ServerNetMode> {
             (
                 me.entity(),
                 id,
-                me.catchup_state.clone(),
+                me.catchup_state.generators.clone(),
                 me.manager.clone(),
             )
         };
@@ -538,6 +1366,10 @@ pub use rpc_builder;
 pub struct RpcNodeBuilder<'a, P, M: RpcNetMode> {
     pub node: &'a Obj<RpcNode<M>>,
     pub path: P,
+
+    // The channel over which senders created by this builder send their messages. Receivers accept
+    // messages on any channel.
+    pub channel: RpcChannel,
 }
 
 impl<'a, M: RpcNetMode> RpcNodeBuilder<'a, EmptyPathBuilder, M> {
@@ -545,6 +1377,7 @@ impl<'a, M: RpcNetMode> RpcNodeBuilder<'a, EmptyPathBuilder, M> {
         Self {
             node,
             path: EmptyPathBuilder,
+            channel: RpcChannel::default(),
         }
     }
 }
@@ -559,8 +1392,14 @@ impl<'a, P, M: RpcNetMode> RpcNodeBuilder<'a, P, M> {
         RpcNodeBuilder {
             node: self.node,
             path: self.path.sub(part),
+            channel: self.channel,
         }
     }
+
+    pub fn on_channel(mut self, channel: RpcChannel) -> Self {
+        self.channel = channel;
+        self
+    }
 }
 
 impl<P: RpcPath, M: RpcNetMode> RpcNodeBuilder<'_, P, M> {
@@ -568,36 +1407,77 @@ impl<P: RpcPath, M: RpcNetMode> RpcNodeBuilder<'_, P, M> {
         RpcNodeSender {
             node: self.node.clone(),
             path: self.path.index(),
+            channel: self.channel,
         }
     }
 
-    pub fn bind_message_raw(
-        self,
-        handler: impl 'static + Fn(M::Peer, Entity, &Bytes) -> anyhow::Result<()>,
-    ) where
-        P: 'static,
-    {
+    fn bind_handler(self, authority: RpcAuthority, handler: RpcMessageHandler<M::Peer>) {
         let mut me = self.node.get_mut();
 
         let slot = ensure_index(&mut me.message_handlers, self.path.index() as usize);
         debug_assert!(slot.is_none());
 
-        *slot = Some(RpcMessageHandler::new(move |peer, target, data| {
-            handler(peer, target, data)
-        }));
+        *slot = Some((authority, handler));
+    }
+
+    pub fn bind_message_raw(
+        self,
+        authority: RpcAuthority,
+        handler: impl 'static + Fn(M::Peer, Entity, &Bytes) -> anyhow::Result<()>,
+    ) {
+        self.bind_handler(
+            authority,
+            RpcMessageHandler::new(move |peer, target, _call_id, data| handler(peer, target, data)),
+        );
     }
 
     pub fn bind_message<D>(
         self,
+        authority: RpcAuthority,
         handler: impl 'static + Fn(M::Peer, Entity, D) -> anyhow::Result<()>,
     ) where
-        P: 'static,
         D: DeserializeOwned,
     {
-        self.bind_message_raw(move |peer, target, data| {
-            handler(peer, target, decode_packet::<D>(data)?)
+        self.bind_message_raw(authority, move |peer, target, data| {
+            handler(peer, target, decode_message::<D>(data)?)
         });
     }
+
+    pub fn bind_request<D, R>(
+        self,
+        authority: RpcAuthority,
+        handler: impl 'static + Fn(M::Peer, Entity, D) -> anyhow::Result<R>,
+    ) where
+        D: DeserializeOwned,
+        R: Serialize,
+    {
+        let node = self.node.clone();
+
+        let handler = RpcMessageHandler::new(move |peer, target, call_id, data| {
+            let Some(call_id) = call_id else {
+                return Err(anyhow::Error::new(RpcDecodeError(anyhow::anyhow!(
+                    "expected a request but got a plain message"
+                ))));
+            };
+
+            let result = decode_message::<D>(data).and_then(|data| handler(peer, target, data));
+
+            // The caller is always told about failures, even though we also report them locally.
+            let (reply, result) = match result {
+                Ok(response) => (Ok(encode_packet(&response)), Ok(())),
+                Err(err) => (Err(REJECTED_REQUEST_REPLY.to_string()), Err(err)),
+            };
+
+            node.get()
+                .manager
+                .get_mut()
+                .queue_reply(peer, call_id, reply);
+
+            result
+        });
+
+        self.bind_handler(authority, handler);
+    }
 }
 
 impl<P: RpcPath> ServerRpcNodeBuilder<'_, P> {
@@ -605,6 +1485,7 @@ impl<P: RpcPath> ServerRpcNodeBuilder<'_, P> {
         self.node
             .get_mut()
             .catchup_state
+            .generators
             .push((self.path.index(), RpcCatchupGenerator::new(handler)));
     }
 
@@ -640,6 +1521,7 @@ impl<P: RpcPath> ClientRpcNodeBuilder<'_, P> {
 pub struct RpcNodeSender<M: RpcNetMode> {
     pub node: Obj<RpcNode<M>>,
     pub path: u32,
+    pub channel: RpcChannel,
 }
 
 impl<M: RpcNetMode> RpcNodeSender<M> {
@@ -648,10 +1530,256 @@ impl<M: RpcNetMode> RpcNodeSender<M> {
 
         node.manager
             .get_mut()
-            .queue_message(peer, node.id, self.path, data);
+            .queue_message_on(self.channel, peer, node.id, self.path, data);
     }
 
     pub fn send<D: Serialize>(&self, peer: M::Peer, data: &D) {
         self.send_raw(peer, encode_packet(data))
     }
+
+    // Calls are always sent reliably, regardless of the sender's channel, since their callers
+    // expect a reply.
+    pub fn call<D: Serialize, R: DeserializeOwned>(
+        &self,
+        peer: M::Peer,
+        data: &D,
+        timeout: Duration,
+    ) -> RpcCall<R> {
+        let node = self.node.get();
+        let mut manager = node.manager.get_mut();
+
+        let (call_id, state) = manager.begin_call(peer, timeout);
+        manager.queue_request(peer, node.id, self.path, call_id, encode_packet(data));
+
+        RpcCall {
+            _ty: PhantomData,
+            state,
+        }
+    }
+}
+
+impl RpcNodeSender<ServerNetMode> {
+    // Broadcasts only ever target peers which have been caught up on the node since other peers
+    // don't know that it exists.
+    pub fn broadcast_where_raw(&self, mut filter: impl FnMut(Entity) -> bool, data: Bytes) {
+        let node = self.node.get();
+        let mut manager = node.manager.get_mut();
+
+        for peer in node.observers() {
+            if filter(peer) {
+                manager.queue_message_on(self.channel, peer, node.id, self.path, data.clone());
+            }
+        }
+    }
+
+    pub fn broadcast_where<D: Serialize>(&self, filter: impl FnMut(Entity) -> bool, data: &D) {
+        self.broadcast_where_raw(filter, encode_packet(data));
+    }
+
+    pub fn broadcast<D: Serialize>(&self, data: &D) {
+        self.broadcast_where(|_| true, data);
+    }
+
+    pub fn broadcast_except<D: Serialize>(&self, except: Entity, data: &D) {
+        self.broadcast_where(|peer| peer != except, data);
+    }
+}
+
+#[derive_where(Debug)]
+pub struct RpcCall<R> {
+    _ty: PhantomData<fn() -> R>,
+    state: RpcCallState,
+}
+
+impl<R: DeserializeOwned> RpcCall<R> {
+    // Yields the call's result exactly once. Returns `None` while the call is still pending.
+    pub fn poll(&self) -> Option<Result<R, RpcCallError>> {
+        let result = self.state.borrow_mut().take()?;
+        Some(result.and_then(|data| decode_packet(&data).map_err(RpcCallError::Decode)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use aunty::StrongEntity;
+
+    use crate::{rpc_path, util::game::transform::Transform};
+
+    use super::*;
+
+    rpc_path! {
+        enum TestRpcs {
+            Pick,
+            Motion,
+        }
+    }
+
+    fn create_root() -> StrongEntity {
+        StrongEntity::new()
+            .with_cyclic(Transform::new(None))
+            .with(ClientRpcManager::default())
+            .with_cyclic(ClientRpcNode::new(RpcNodeId::ROOT))
+    }
+
+    fn pick_packet(entity: NetEntity) -> RpcPacket {
+        RpcPacket {
+            messages: vec![RpcPacketMessage {
+                node_id: RpcNodeId::ROOT.0.get(),
+                path: TestRpcs::Pick.as_index(),
+                call_id: None,
+                data: encode_packet(&entity),
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn motion_packet(sequence: u32, motion: u32) -> RpcPacket {
+        RpcPacket {
+            sequenced: vec![RpcPacketMessage {
+                node_id: RpcNodeId::ROOT.0.get(),
+                path: TestRpcs::Motion.as_index(),
+                call_id: None,
+                data: encode_packet(&motion),
+            }],
+            sequence,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn net_entities_resolve_against_the_receiver() {
+        let root = create_root();
+        let picked = Rc::new(Cell::new(None));
+
+        root.obj::<ClientRpcNode>()
+            .builder()
+            .sub(TestRpcs::Pick)
+            .bind_message(RpcAuthority::ServerOnly, {
+                let picked = picked.clone();
+                move |(), target, entity: NetEntity| {
+                    let node = target.get::<ClientRpcNode>().resolve(entity)?;
+                    picked.set(Some(node.get().entity()));
+                    Ok(())
+                }
+            });
+
+        let item_id = RpcNodeId(NonZeroU64::new(42).unwrap());
+        let item = StrongEntity::new()
+            .with_cyclic(Transform::new(Some(root.obj())))
+            .with_cyclic(ClientRpcNode::new(item_id));
+
+        // References to known nodes resolve to the receiver's copy of the node.
+        let errors = root
+            .obj::<ClientRpcManager>()
+            .process_packet((), &pick_packet(NetEntity(item_id)));
+
+        assert!(errors.is_empty(), "unexpected RPC errors: {errors:?}");
+        assert_eq!(picked.take(), Some(item.entity()));
+
+        // References to unknown nodes are reported without being mistaken for malformed payloads.
+        item.get::<ClientRpcNode>().despawn();
+
+        let errors = root
+            .obj::<ClientRpcManager>()
+            .process_packet((), &pick_packet(NetEntity(item_id)));
+
+        assert_eq!(picked.take(), None);
+        assert!(matches!(
+            errors.as_slice(),
+            [RpcError::UnknownReference { referenced, .. }] if *referenced == item_id
+        ));
+    }
+
+    #[test]
+    fn sequenced_messages_are_coalesced_per_path() {
+        let mut manager = ServerRpcManager::default();
+        let peer = StrongEntity::new();
+        let other = RpcNodeId(NonZeroU64::new(42).unwrap());
+
+        for (node, motion) in [(RpcNodeId::ROOT, 1u32), (other, 2), (RpcNodeId::ROOT, 3)] {
+            manager.queue_message_on(
+                RpcChannel::UnreliableSequenced,
+                peer.entity(),
+                node,
+                TestRpcs::Motion.as_index(),
+                encode_packet(&motion),
+            );
+        }
+
+        let packets = manager.drain_queues().collect::<Vec<_>>();
+        let [(_, RpcDelivery::Unreliable, packet)] = packets.as_slice() else {
+            panic!("expected a single unreliable packet, got {packets:?}");
+        };
+
+        // Only the newest message for each path is sent, in the position of the first one.
+        let sent = packet
+            .sequenced
+            .iter()
+            .map(|part| (part.node_id, decode_packet::<u32>(&part.data).unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(sent, [(RpcNodeId::ROOT.0.get(), 3), (other.0.get(), 2)]);
+    }
+
+    #[test]
+    fn sequenced_messages_are_delivered_at_most_once() {
+        let root = create_root();
+        let received = Rc::new(RefCell::new(Vec::new()));
+
+        root.obj::<ClientRpcNode>()
+            .builder()
+            .sub(TestRpcs::Motion)
+            .bind_message(RpcAuthority::ServerOnly, {
+                let received = received.clone();
+                move |(), _, motion: u32| {
+                    received.borrow_mut().push(motion);
+                    Ok(())
+                }
+            });
+
+        let manager = root.obj::<ClientRpcManager>();
+
+        // Duplicates and stale packets are dropped.
+        for (sequence, motion) in [(2, 2), (2, 2), (1, 1), (3, 3), (3, 3)] {
+            let errors = manager.process_packet((), &motion_packet(sequence, motion));
+            assert!(errors.is_empty(), "unexpected RPC errors: {errors:?}");
+        }
+
+        assert_eq!(*received.borrow(), [2, 3]);
+    }
+
+    #[test]
+    fn rejected_requests_are_answered_immediately() {
+        let client = create_root();
+        let server = StrongEntity::new()
+            .with_cyclic(Transform::new(None))
+            .with(ServerRpcManager::default())
+            .with_cyclic(ServerRpcNode::new(RpcNodeId::ROOT));
+        let peer = StrongEntity::new();
+
+        // The server never bound this path so it rejects the request before any handler runs.
+        let call = client
+            .obj::<ClientRpcNode>()
+            .builder()
+            .sub(TestRpcs::Pick)
+            .sender()
+            .call::<_, ()>((), &NetEntity(RpcNodeId::ROOT), Duration::from_secs(60));
+
+        for ((), _, packet) in client.get_mut::<ClientRpcManager>().drain_queues() {
+            let errors = server
+                .obj::<ServerRpcManager>()
+                .process_packet(peer.entity(), &packet);
+
+            assert!(matches!(errors.as_slice(), [RpcError::UnknownPath { .. }]));
+        }
+
+        for (_, _, packet) in server.get_mut::<ServerRpcManager>().drain_queues() {
+            let errors = client.obj::<ClientRpcManager>().process_packet((), &packet);
+            assert!(errors.is_empty(), "unexpected RPC errors: {errors:?}");
+        }
+
+        assert!(matches!(call.poll(), Some(Err(RpcCallError::Remote(_)))));
+    }
 }
@@ -0,0 +1,539 @@
+use std::{
+    cell::{Cell, Ref, RefCell, RefMut},
+    rc::Rc,
+};
+
+use aunty::delegate;
+use bytes::Bytes;
+use derive_where::derive_where;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::rpc::{encode_packet, rpc_builder, RpcAuthority, RpcFlushHandler, ServerRpcNodeSender};
+
+// === Handlers === //
+
+delegate! {
+    pub fn SyncChangeHandler<T>(value: &T)
+}
+
+delegate! {
+    pub fn SyncListOpHandler<T>(op: &SyncListOp<T>)
+}
+
+// === ServerSyncValue === //
+
+// A value whose changes are replicated to every peer observing the node it was bound to. Changes
+// are coalesced and sent once per flush of the node's `RpcManager`, which the server runs once per
+// tick.
+#[derive_where(Clone)]
+#[derive_where(Debug; T)]
+pub struct ServerSyncValue<T> {
+    inner: Rc<ServerSyncInner<T>>,
+}
+
+#[derive_where(Debug; T)]
+struct ServerSyncInner<T> {
+    value: RefCell<T>,
+    dirty: Cell<bool>,
+    sender: ServerRpcNodeSender,
+}
+
+impl<T: 'static + Serialize> ServerSyncValue<T> {
+    pub fn new(builder: rpc_builder!(server), value: T) -> Self {
+        let inner = Rc::new(ServerSyncInner {
+            value: RefCell::new(value),
+            dirty: Cell::new(false),
+            sender: builder.sender(),
+        });
+
+        builder.bind_catchup_raw({
+            let inner = inner.clone();
+            move |_peer, _target| encode_packet(&*inner.value.borrow())
+        });
+
+        Self { inner }
+    }
+
+    pub fn get(&self) -> Ref<'_, T> {
+        self.inner.value.borrow()
+    }
+
+    // Marks the value as dirty even if it isn't actually modified through the returned guard.
+    pub fn get_mut(&self) -> RefMut<'_, T> {
+        self.mark_dirty();
+        self.inner.value.borrow_mut()
+    }
+
+    pub fn set(&self, value: T) {
+        *self.get_mut() = value;
+    }
+
+    pub fn mark_dirty(&self) {
+        if self.inner.dirty.replace(true) {
+            return;
+        }
+
+        let inner = self.inner.clone();
+        let handler = RpcFlushHandler::new(move || {
+            inner.dirty.set(false);
+
+            let data = encode_packet(&*inner.value.borrow());
+            inner.sender.broadcast_where_raw(|_| true, data);
+        });
+
+        // The flush is cancelled if the node despawns before then since there'd be nobody left to
+        // send the value to.
+        let node = self.inner.sender.node.get();
+        node.manager()
+            .get_mut()
+            .queue_node_flush(node.id(), handler);
+    }
+}
+
+// === ClientSyncValue === //
+
+#[derive_where(Clone)]
+#[derive_where(Debug; T)]
+pub struct ClientSyncValue<T> {
+    inner: Rc<ClientSyncInner<T>>,
+}
+
+#[derive_where(Debug; T)]
+struct ClientSyncInner<T> {
+    value: RefCell<T>,
+    handlers: RefCell<Vec<SyncChangeHandler<T>>>,
+}
+
+impl<T: 'static + DeserializeOwned> ClientSyncValue<T> {
+    // This must be called while the node's catchup packet is being processed, which is typically
+    // the case when constructing a node in response to its spawn message.
+    pub fn new(builder: rpc_builder!(client)) -> anyhow::Result<Self> {
+        let inner = Rc::new(ClientSyncInner {
+            value: RefCell::new(builder.read_catchup::<T>()?),
+            handlers: RefCell::default(),
+        });
+
+        builder.bind_message(RpcAuthority::ServerOnly, {
+            let inner = inner.clone();
+            move |(), _target, value: T| {
+                *inner.value.borrow_mut() = value;
+
+                // Clone the handlers so that they can register more handlers.
+                let handlers = inner.handlers.borrow().clone();
+                for handler in handlers {
+                    handler.call(&inner.value.borrow());
+                }
+
+                Ok(())
+            }
+        });
+
+        Ok(Self { inner })
+    }
+
+    pub fn get(&self) -> Ref<'_, T> {
+        self.inner.value.borrow()
+    }
+
+    pub fn on_change(&self, handler: SyncChangeHandler<T>) {
+        self.inner.handlers.borrow_mut().push(handler);
+    }
+}
+
+// === SyncListOp === //
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SyncListOp<T> {
+    Insert { index: u32, value: T },
+    Remove { index: u32 },
+    Update { index: u32, value: T },
+    Move { from: u32, to: u32 },
+}
+
+impl<T> SyncListOp<T> {
+    fn apply(self, values: &mut Vec<T>) -> anyhow::Result<()> {
+        let len = values.len();
+        let check = |index: u32, bound: usize| {
+            if index as usize >= bound {
+                anyhow::bail!("list index {index} is out of bounds for a list of length {len}");
+            }
+            Ok(index as usize)
+        };
+
+        match self {
+            SyncListOp::Insert { index, value } => {
+                values.insert(check(index, len + 1)?, value);
+            }
+            SyncListOp::Remove { index } => {
+                values.remove(check(index, len)?);
+            }
+            SyncListOp::Update { index, value } => {
+                values[check(index, len)?] = value;
+            }
+            SyncListOp::Move { from, to } => {
+                let from = check(from, len)?;
+                let to = check(to, len)?;
+                let value = values.remove(from);
+                values.insert(to, value);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// === ServerSyncList === //
+
+// An ordered list whose operations are replicated to every peer observing the node it was bound to.
+// Unlike `ServerSyncValue`, operations are sent immediately since they can't be coalesced. Each
+// operation is applied locally before it is sent so an out-of-bounds index panics without ever
+// reaching observers.
+#[derive_where(Clone)]
+#[derive_where(Debug; T)]
+pub struct ServerSyncList<T> {
+    inner: Rc<ServerSyncListInner<T>>,
+}
+
+#[derive_where(Debug; T)]
+struct ServerSyncListInner<T> {
+    values: RefCell<Vec<T>>,
+    sender: ServerRpcNodeSender,
+}
+
+impl<T: 'static + Serialize> ServerSyncList<T> {
+    pub fn new(builder: rpc_builder!(server), values: Vec<T>) -> Self {
+        let inner = Rc::new(ServerSyncListInner {
+            values: RefCell::new(values),
+            sender: builder.sender(),
+        });
+
+        builder.bind_catchup_raw({
+            let inner = inner.clone();
+            move |_peer, _target| encode_packet(&*inner.values.borrow())
+        });
+
+        Self { inner }
+    }
+
+    pub fn get(&self) -> Ref<'_, [T]> {
+        Ref::map(self.inner.values.borrow(), Vec::as_slice)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.values.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&self, value: T) {
+        self.insert(self.len(), value);
+    }
+
+    pub fn insert(&self, index: usize, value: T) {
+        let op = encode_packet(&SyncListOp::Insert {
+            index: index as u32,
+            value: &value,
+        });
+        self.inner.values.borrow_mut().insert(index, value);
+        self.broadcast(op);
+    }
+
+    pub fn remove(&self, index: usize) -> T {
+        let value = self.inner.values.borrow_mut().remove(index);
+        self.broadcast(encode_packet(&SyncListOp::<T>::Remove {
+            index: index as u32,
+        }));
+        value
+    }
+
+    pub fn update(&self, index: usize, value: T) -> T {
+        let op = encode_packet(&SyncListOp::Update {
+            index: index as u32,
+            value: &value,
+        });
+        let old = std::mem::replace(&mut self.inner.values.borrow_mut()[index], value);
+        self.broadcast(op);
+        old
+    }
+
+    pub fn move_item(&self, from: usize, to: usize) {
+        {
+            let mut values = self.inner.values.borrow_mut();
+            let len = values.len();
+            assert!(
+                from < len && to < len,
+                "cannot move item {from} to {to} in a list of length {len}"
+            );
+
+            let value = values.remove(from);
+            values.insert(to, value);
+        }
+
+        self.broadcast(encode_packet(&SyncListOp::<T>::Move {
+            from: from as u32,
+            to: to as u32,
+        }));
+    }
+
+    fn broadcast(&self, op: Bytes) {
+        self.inner.sender.broadcast_where_raw(|_| true, op);
+    }
+}
+
+// === ClientSyncList === //
+
+#[derive_where(Clone)]
+#[derive_where(Debug; T)]
+pub struct ClientSyncList<T> {
+    inner: Rc<ClientSyncListInner<T>>,
+}
+
+#[derive_where(Debug; T)]
+struct ClientSyncListInner<T> {
+    values: RefCell<Vec<T>>,
+    handlers: RefCell<Vec<SyncListOpHandler<T>>>,
+}
+
+impl<T: 'static + Clone + DeserializeOwned> ClientSyncList<T> {
+    // Like `ClientSyncValue::new`, this must be called while the node's catchup packet is being
+    // processed.
+    pub fn new(builder: rpc_builder!(client)) -> anyhow::Result<Self> {
+        let inner = Rc::new(ClientSyncListInner {
+            values: RefCell::new(builder.read_catchup::<Vec<T>>()?),
+            handlers: RefCell::default(),
+        });
+
+        builder.bind_message(RpcAuthority::ServerOnly, {
+            let inner = inner.clone();
+            move |(), _target, op: SyncListOp<T>| {
+                op.clone().apply(&mut inner.values.borrow_mut())?;
+
+                let handlers = inner.handlers.borrow().clone();
+                for handler in handlers {
+                    handler.call(&op);
+                }
+
+                Ok(())
+            }
+        });
+
+        Ok(Self { inner })
+    }
+
+    pub fn get(&self) -> Ref<'_, [T]> {
+        Ref::map(self.inner.values.borrow(), Vec::as_slice)
+    }
+
+    // Handlers are called after the operation has been applied.
+    pub fn on_op(&self, handler: SyncListOpHandler<T>) {
+        self.inner.handlers.borrow_mut().push(handler);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use aunty::{Entity, StrongEntity};
+
+    use crate::{
+        rpc_path,
+        util::game::{
+            rpc::{decode_packet, RpcNodeId, RpcPacket, ServerRpcManager, ServerRpcNode},
+            transform::Transform,
+        },
+    };
+
+    use super::*;
+
+    rpc_path! {
+        enum TestRpcs {
+            Value,
+            List,
+        }
+    }
+
+    fn create_root() -> StrongEntity {
+        StrongEntity::new()
+            .with_cyclic(Transform::new(None))
+            .with(ServerRpcManager::default())
+            .with_cyclic(ServerRpcNode::new(RpcNodeId::ROOT))
+    }
+
+    fn flush(root: &StrongEntity) -> Vec<(Entity, RpcPacket)> {
+        let manager = root.obj::<ServerRpcManager>();
+        manager.run_flushes();
+
+        let packets = manager
+            .get_mut()
+            .drain_queues()
+            .map(|(peer, _, packet)| (peer, packet))
+            .collect();
+
+        packets
+    }
+
+    fn create_value(root: &StrongEntity, value: u32) -> ServerSyncValue<u32> {
+        let node = root.obj::<ServerRpcNode>();
+        ServerSyncValue::new(node.builder().sub(TestRpcs::Value), value)
+    }
+
+    #[test]
+    fn value_changes_are_coalesced() {
+        let root = create_root();
+        let peer = StrongEntity::new();
+        let value = create_value(&root, 0);
+
+        root.obj::<ServerRpcNode>().queue_catchup(peer.entity());
+        flush(&root);
+
+        value.set(1);
+        value.set(2);
+        *value.get_mut() += 1;
+
+        let packets = flush(&root);
+        assert_eq!(packets.len(), 1);
+
+        let (target, packet) = &packets[0];
+        assert_eq!(*target, peer.entity());
+        assert_eq!(packet.messages.len(), 1);
+        assert_eq!(decode_packet::<u32>(&packet.messages[0].data).unwrap(), 3);
+
+        // Nothing is sent until the value changes again.
+        assert!(flush(&root).is_empty());
+    }
+
+    #[test]
+    fn value_changes_follow_catchup() {
+        let root = create_root();
+        let peer = StrongEntity::new();
+        let value = create_value(&root, 4);
+
+        root.obj::<ServerRpcNode>().queue_catchup(peer.entity());
+        let packets = flush(&root);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].1.catchup.len(), 1);
+        assert!(packets[0].1.messages.is_empty());
+        assert_eq!(
+            decode_packet::<u32>(&packets[0].1.catchup[0].data).unwrap(),
+            4
+        );
+
+        value.set(5);
+
+        let packets = flush(&root);
+        assert_eq!(packets.len(), 1);
+        assert!(packets[0].1.catchup.is_empty());
+        assert_eq!(packets[0].1.messages.len(), 1);
+        assert_eq!(
+            decode_packet::<u32>(&packets[0].1.messages[0].data).unwrap(),
+            5
+        );
+    }
+
+    #[test]
+    fn despawn_cancels_pending_flush() {
+        let root = create_root();
+        let child = StrongEntity::new()
+            .with_cyclic(Transform::new(Some(root.obj())))
+            .with_cyclic(ServerRpcNode::new(RpcNodeId(2.try_into().unwrap())));
+
+        let value = {
+            let node = child.obj::<ServerRpcNode>();
+            ServerSyncValue::new(node.builder().sub(TestRpcs::Value), 0)
+        };
+
+        value.set(1);
+        child.get::<ServerRpcNode>().despawn();
+        drop(child);
+
+        assert!(flush(&root).is_empty());
+    }
+
+    fn apply(values: &[u32], op: SyncListOp<u32>) -> anyhow::Result<Vec<u32>> {
+        let mut values = values.to_vec();
+        op.apply(&mut values)?;
+        Ok(values)
+    }
+
+    #[test]
+    fn list_ops_apply_in_bounds() {
+        let values = [1, 2, 3];
+
+        assert_eq!(
+            apply(&values, SyncListOp::Insert { index: 3, value: 4 }).unwrap(),
+            [1, 2, 3, 4]
+        );
+        assert_eq!(
+            apply(&values, SyncListOp::Insert { index: 0, value: 0 }).unwrap(),
+            [0, 1, 2, 3]
+        );
+        assert_eq!(
+            apply(&values, SyncListOp::Remove { index: 1 }).unwrap(),
+            [1, 3]
+        );
+        assert_eq!(
+            apply(&values, SyncListOp::Update { index: 2, value: 9 }).unwrap(),
+            [1, 2, 9]
+        );
+        assert_eq!(
+            apply(&values, SyncListOp::Move { from: 0, to: 2 }).unwrap(),
+            [2, 3, 1]
+        );
+        assert_eq!(
+            apply(&values, SyncListOp::Move { from: 2, to: 0 }).unwrap(),
+            [3, 1, 2]
+        );
+    }
+
+    #[test]
+    fn list_ops_reject_out_of_bounds() {
+        let values = [1, 2, 3];
+
+        assert!(apply(&values, SyncListOp::Insert { index: 4, value: 4 }).is_err());
+        assert!(apply(&values, SyncListOp::Remove { index: 3 }).is_err());
+        assert!(apply(&values, SyncListOp::Update { index: 3, value: 9 }).is_err());
+        assert!(apply(&values, SyncListOp::Move { from: 3, to: 0 }).is_err());
+        assert!(apply(&values, SyncListOp::Move { from: 0, to: 3 }).is_err());
+        assert!(apply(&[], SyncListOp::<u32>::Remove { index: 0 }).is_err());
+    }
+
+    #[test]
+    fn list_ops_are_applied_before_being_sent() {
+        let root = create_root();
+        let peer = StrongEntity::new();
+        let list = {
+            let node = root.obj::<ServerRpcNode>();
+            ServerSyncList::new(node.builder().sub(TestRpcs::List), vec![1u32, 2])
+        };
+
+        root.obj::<ServerRpcNode>().queue_catchup(peer.entity());
+        flush(&root);
+
+        // Invalid operations panic without reaching observers.
+        assert!(catch_unwind(AssertUnwindSafe(|| list.insert(3, 3))).is_err());
+        assert!(catch_unwind(AssertUnwindSafe(|| list.update(2, 3))).is_err());
+        assert!(flush(&root).is_empty());
+        assert_eq!(&*list.get(), [1, 2]);
+
+        // Valid ones are replayed by observers in the same order.
+        list.push(3);
+        list.move_item(2, 0);
+        list.update(1, 5);
+        assert_eq!(list.remove(2), 2);
+
+        let packets = flush(&root);
+        assert_eq!(packets.len(), 1);
+
+        let mut replica = vec![1, 2];
+        for message in &packets[0].1.messages {
+            decode_packet::<SyncListOp<u32>>(&message.data)
+                .unwrap()
+                .apply(&mut replica)
+                .unwrap();
+        }
+        assert_eq!(replica, &*list.get());
+    }
+}
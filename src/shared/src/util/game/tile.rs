@@ -184,8 +184,8 @@ pub struct RayIntersection {
 
 // === TileLayerData === //
 
-const CHUNK_EDGE: i32 = 16;
-const CHUNK_AREA: i32 = CHUNK_EDGE * CHUNK_EDGE;
+pub const CHUNK_EDGE: i32 = 16;
+pub const CHUNK_AREA: i32 = CHUNK_EDGE * CHUNK_EDGE;
 
 fn decompose_world_pos(v: IVec2) -> (IVec2, IVec2) {
     let IVec2 { x, y } = v;
@@ -256,6 +256,29 @@ impl TileLayerData {
         let delta = is_not_air - was_not_air;
         cache.non_air_count += delta;
     }
+
+    pub fn chunks(&self) -> impl Iterator<Item = IVec2> + '_ {
+        let cached = self
+            .cache
+            .as_ref()
+            .filter(|cache| cache.non_air_count > 0)
+            .map(|_| self.cache_pos);
+
+        self.chunks.keys().copied().chain(cached)
+    }
+
+    pub fn chunk(&mut self, chunk: IVec2) -> Option<&[u16; CHUNK_AREA as usize]> {
+        self.update_cache(chunk);
+        self.cache.as_ref().map(|cache| &*cache.data)
+    }
+
+    pub fn set_chunk(&mut self, chunk: IVec2, data: &[u16; CHUNK_AREA as usize]) {
+        self.update_cache(chunk);
+        self.cache = Some(TileChunk {
+            non_air_count: data.iter().filter(|&&tile| tile != 0).count() as i32,
+            data: Box::new(*data),
+        });
+    }
 }
 
 // === MaterialRegistry === //
@@ -286,6 +309,10 @@ impl MaterialRegistry {
     pub fn get_by_name(&self, name: &str) -> MaterialInfo {
         self.get(self.by_name[name])
     }
+
+    pub fn try_get(&self, id: u16) -> Option<MaterialInfo> {
+        ((id as usize) < self.by_id.len()).then(|| self.get(id))
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
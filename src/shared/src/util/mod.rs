@@ -1,3 +1,4 @@
 pub mod game;
 pub mod lang;
 pub mod math;
+pub mod net;
@@ -0,0 +1,158 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+// === Datagram === //
+
+const DATAGRAM_KIND_HELLO: u8 = 0;
+const DATAGRAM_KIND_DATA: u8 = 1;
+
+// Datagrams are small enough to avoid fragmentation on pretty much every network path. Larger
+// payloads should be sent over the reliable stream instead.
+pub const MAX_DATAGRAM_SIZE: usize = 1200;
+
+const CLIENT_HEADER_SIZE: usize = 8 + 1;
+const SERVER_HEADER_SIZE: usize = 1;
+
+pub const MAX_CLIENT_DATAGRAM_PAYLOAD: usize = MAX_DATAGRAM_SIZE - CLIENT_HEADER_SIZE;
+pub const MAX_SERVER_DATAGRAM_PAYLOAD: usize = MAX_DATAGRAM_SIZE - SERVER_HEADER_SIZE;
+
+// Datagrams travel alongside a reliable stream, which tells the client which token to use. Clients
+// prefix every datagram with that token, followed by a single byte indicating the datagram's kind,
+// so that the server can tell which peer sent it. The server only sends datagrams to the address
+// from which it last received a valid datagram so its datagrams just carry the kind.
+#[derive(Debug, Clone)]
+pub enum Datagram {
+    // Lets the server learn our address and keeps NAT mappings alive. Only sent by clients.
+    Hello,
+    Data(Bytes),
+}
+
+impl Datagram {
+    fn encode_body(&self, dst: &mut BytesMut) {
+        match self {
+            Datagram::Hello => dst.put_u8(DATAGRAM_KIND_HELLO),
+            Datagram::Data(data) => {
+                dst.put_u8(DATAGRAM_KIND_DATA);
+                dst.put(&data[..]);
+            }
+        }
+    }
+
+    fn decode_body(mut src: Bytes) -> anyhow::Result<Self> {
+        if src.is_empty() {
+            anyhow::bail!("datagram is missing its kind");
+        }
+
+        match src.get_u8() {
+            DATAGRAM_KIND_HELLO => Ok(Datagram::Hello),
+            DATAGRAM_KIND_DATA => Ok(Datagram::Data(src)),
+            kind => anyhow::bail!("datagram has unknown kind {kind}"),
+        }
+    }
+
+    fn payload_len(&self) -> usize {
+        match self {
+            Datagram::Hello => 0,
+            Datagram::Data(data) => data.len(),
+        }
+    }
+
+    // Returns `None` if the datagram wouldn't fit in `MAX_DATAGRAM_SIZE`.
+    pub fn encode_client(&self, token: u64) -> Option<Bytes> {
+        if self.payload_len() > MAX_CLIENT_DATAGRAM_PAYLOAD {
+            return None;
+        }
+
+        let mut dst = BytesMut::with_capacity(CLIENT_HEADER_SIZE + self.payload_len());
+        dst.put_u64(token);
+        self.encode_body(&mut dst);
+        Some(dst.freeze())
+    }
+
+    pub fn decode_client(mut src: Bytes) -> anyhow::Result<(u64, Self)> {
+        if src.len() < 8 {
+            anyhow::bail!("datagram is missing its token");
+        }
+
+        let token = src.get_u64();
+        Ok((token, Self::decode_body(src)?))
+    }
+
+    // Returns `None` if the datagram wouldn't fit in `MAX_DATAGRAM_SIZE`.
+    pub fn encode_server(&self) -> Option<Bytes> {
+        if self.payload_len() > MAX_SERVER_DATAGRAM_PAYLOAD {
+            return None;
+        }
+
+        let mut dst = BytesMut::with_capacity(SERVER_HEADER_SIZE + self.payload_len());
+        self.encode_body(&mut dst);
+        Some(dst.freeze())
+    }
+
+    pub fn decode_server(src: Bytes) -> anyhow::Result<Self> {
+        Self::decode_body(src)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_datagrams_round_trip() {
+        let token = 0x0123_4567_89ab_cdef;
+
+        let encoded = Datagram::Hello.encode_client(token).unwrap();
+        assert_eq!(encoded.len(), CLIENT_HEADER_SIZE);
+        assert!(matches!(
+            Datagram::decode_client(encoded).unwrap(),
+            (decoded, Datagram::Hello) if decoded == token
+        ));
+
+        let data = Bytes::from_static(b"hello world");
+        let encoded = Datagram::Data(data.clone()).encode_client(token).unwrap();
+        assert!(matches!(
+            Datagram::decode_client(encoded).unwrap(),
+            (decoded, Datagram::Data(decoded_data)) if decoded == token && decoded_data == data
+        ));
+    }
+
+    #[test]
+    fn server_datagrams_round_trip() {
+        let encoded = Datagram::Hello.encode_server().unwrap();
+        assert!(matches!(
+            Datagram::decode_server(encoded).unwrap(),
+            Datagram::Hello
+        ));
+
+        let data = Bytes::from_static(b"hello world");
+        let encoded = Datagram::Data(data.clone()).encode_server().unwrap();
+        assert_eq!(encoded.len(), SERVER_HEADER_SIZE + data.len());
+        assert!(matches!(
+            Datagram::decode_server(encoded).unwrap(),
+            Datagram::Data(decoded) if decoded == data
+        ));
+    }
+
+    #[test]
+    fn oversized_datagrams_are_not_encoded() {
+        let fits = Datagram::Data(Bytes::from(vec![0; MAX_CLIENT_DATAGRAM_PAYLOAD]));
+        assert_eq!(fits.encode_client(0).unwrap().len(), MAX_DATAGRAM_SIZE);
+
+        let too_big = Datagram::Data(Bytes::from(vec![0; MAX_CLIENT_DATAGRAM_PAYLOAD + 1]));
+        assert!(too_big.encode_client(0).is_none());
+
+        let fits = Datagram::Data(Bytes::from(vec![0; MAX_SERVER_DATAGRAM_PAYLOAD]));
+        assert_eq!(fits.encode_server().unwrap().len(), MAX_DATAGRAM_SIZE);
+
+        let too_big = Datagram::Data(Bytes::from(vec![0; MAX_SERVER_DATAGRAM_PAYLOAD + 1]));
+        assert!(too_big.encode_server().is_none());
+    }
+
+    #[test]
+    fn malformed_datagrams_are_rejected() {
+        assert!(Datagram::decode_client(Bytes::from_static(&[1, 2, 3])).is_err());
+        assert!(Datagram::decode_client(Bytes::from_static(&[0; 8])).is_err());
+        assert!(Datagram::decode_server(Bytes::new()).is_err());
+        assert!(Datagram::decode_server(Bytes::from_static(&[0xff])).is_err());
+    }
+}
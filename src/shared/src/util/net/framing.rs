@@ -0,0 +1,255 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+// === Frame === //
+
+const FRAME_KIND_DATA: u8 = 0;
+const FRAME_KIND_PING: u8 = 1;
+const FRAME_KIND_PONG: u8 = 2;
+const FRAME_KIND_DISCONNECT: u8 = 3;
+const FRAME_KIND_DATAGRAM_TOKEN: u8 = 4;
+
+// Transports handle everything but `Data` frames themselves. Users of a transport only ever see
+// the payloads of `Data` frames.
+#[derive(Debug, Clone)]
+pub enum Frame {
+    Data(Bytes),
+    Ping(u64),
+    Pong(u64),
+    Disconnect(String),
+
+    // Sent by the server right after a peer connects. Datagrams carrying this token are
+    // attributed to the peer. See `datagram`.
+    DatagramToken(u64),
+}
+
+// === FrameCodec === //
+
+const FRAME_HEADER_SIZE: usize = 4;
+
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+// Frames are prefixed by their length as a big-endian `u32` followed by a single byte indicating
+// their kind. Both the client and the server use this codec so that they agree on the wire format.
+#[derive(Debug, Copy, Clone)]
+pub struct FrameCodec {
+    max_frame_size: usize,
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FRAME_SIZE)
+    }
+}
+
+impl FrameCodec {
+    pub fn new(max_frame_size: usize) -> Self {
+        assert!(
+            u32::try_from(max_frame_size).is_ok(),
+            "maximum frame size must fit in a u32"
+        );
+
+        Self { max_frame_size }
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    pub fn encode(&self, frame: &Frame, dst: &mut BytesMut) -> anyhow::Result<()> {
+        let nonce_buf;
+        let (kind, body) = match frame {
+            Frame::Data(data) => (FRAME_KIND_DATA, &data[..]),
+            Frame::Ping(nonce) => {
+                nonce_buf = nonce.to_be_bytes();
+                (FRAME_KIND_PING, &nonce_buf[..])
+            }
+            Frame::Pong(nonce) => {
+                nonce_buf = nonce.to_be_bytes();
+                (FRAME_KIND_PONG, &nonce_buf[..])
+            }
+            Frame::Disconnect(reason) => (FRAME_KIND_DISCONNECT, reason.as_bytes()),
+            Frame::DatagramToken(token) => {
+                nonce_buf = token.to_be_bytes();
+                (FRAME_KIND_DATAGRAM_TOKEN, &nonce_buf[..])
+            }
+        };
+
+        let frame_len = 1 + body.len();
+        if frame_len > self.max_frame_size {
+            anyhow::bail!(
+                "attempted to send a frame of {frame_len} byte(s), which exceeds the maximum frame size of {} byte(s)",
+                self.max_frame_size,
+            );
+        }
+
+        dst.reserve(FRAME_HEADER_SIZE + frame_len);
+        dst.put_u32(frame_len as u32);
+        dst.put_u8(kind);
+        dst.put(body);
+        Ok(())
+    }
+
+    pub fn decode(&self, src: &mut BytesMut) -> anyhow::Result<Option<Frame>> {
+        // Read the header without consuming it so that we can resume once more data arrives.
+        let Some(header) = src.get(..FRAME_HEADER_SIZE) else {
+            return Ok(None);
+        };
+        let frame_len = u32::from_be_bytes(header.try_into().unwrap()) as usize;
+
+        if frame_len > self.max_frame_size {
+            anyhow::bail!(
+                "peer sent a frame of {frame_len} byte(s), which exceeds the maximum frame size of {} byte(s)",
+                self.max_frame_size,
+            );
+        }
+
+        // Wait for the rest of the frame to arrive.
+        if src.len() < FRAME_HEADER_SIZE + frame_len {
+            src.reserve(FRAME_HEADER_SIZE + frame_len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(FRAME_HEADER_SIZE);
+        let mut frame = src.split_to(frame_len).freeze();
+
+        // Decode the frame's contents
+        if frame.is_empty() {
+            anyhow::bail!("peer sent a frame without a kind");
+        }
+
+        let kind = frame.get_u8();
+        let frame = match kind {
+            FRAME_KIND_DATA => Frame::Data(frame),
+            FRAME_KIND_PING | FRAME_KIND_PONG => {
+                if frame.len() != 8 {
+                    anyhow::bail!(
+                        "peer sent a heartbeat frame with a body of {} byte(s)",
+                        frame.len()
+                    );
+                }
+
+                let nonce = frame.get_u64();
+                if kind == FRAME_KIND_PING {
+                    Frame::Ping(nonce)
+                } else {
+                    Frame::Pong(nonce)
+                }
+            }
+            FRAME_KIND_DATAGRAM_TOKEN => {
+                if frame.len() != 8 {
+                    anyhow::bail!(
+                        "peer sent a datagram token frame with a body of {} byte(s)",
+                        frame.len()
+                    );
+                }
+
+                Frame::DatagramToken(frame.get_u64())
+            }
+            FRAME_KIND_DISCONNECT => match String::from_utf8(frame.to_vec()) {
+                Ok(reason) => Frame::Disconnect(reason),
+                Err(_) => anyhow::bail!("peer sent a disconnect frame with a non-UTF-8 reason"),
+            },
+            _ => anyhow::bail!("peer sent a frame with unknown kind {kind}"),
+        };
+
+        Ok(Some(frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_all(codec: &FrameCodec, frames: &[Frame]) -> BytesMut {
+        let mut buf = BytesMut::new();
+        for frame in frames {
+            codec.encode(frame, &mut buf).unwrap();
+        }
+        buf
+    }
+
+    #[test]
+    fn partial_header_waits_for_more_data() {
+        let codec = FrameCodec::default();
+        let encoded = encode_all(&codec, &[Frame::Ping(42)]);
+
+        let mut src = BytesMut::from(&encoded[..FRAME_HEADER_SIZE - 1]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        assert_eq!(src.len(), FRAME_HEADER_SIZE - 1);
+
+        src.extend_from_slice(&encoded[FRAME_HEADER_SIZE - 1..]);
+        assert!(matches!(
+            codec.decode(&mut src).unwrap(),
+            Some(Frame::Ping(42))
+        ));
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn partial_body_waits_for_more_data() {
+        let codec = FrameCodec::default();
+        let encoded = encode_all(&codec, &[Frame::Data(Bytes::from_static(b"hello world"))]);
+
+        let split = FRAME_HEADER_SIZE + 3;
+        let mut src = BytesMut::from(&encoded[..split]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        assert_eq!(src.len(), split);
+
+        src.extend_from_slice(&encoded[split..]);
+        match codec.decode(&mut src).unwrap() {
+            Some(Frame::Data(data)) => assert_eq!(&data[..], b"hello world"),
+            frame => panic!("unexpected frame {frame:?}"),
+        }
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn several_frames_in_one_buffer() {
+        let codec = FrameCodec::default();
+        let mut src = encode_all(
+            &codec,
+            &[
+                Frame::Data(Bytes::from_static(b"abc")),
+                Frame::Pong(7),
+                Frame::DatagramToken(u64::MAX),
+                Frame::Disconnect("bye".to_string()),
+            ],
+        );
+
+        assert!(
+            matches!(codec.decode(&mut src).unwrap(), Some(Frame::Data(data)) if &data[..] == b"abc")
+        );
+        assert!(matches!(
+            codec.decode(&mut src).unwrap(),
+            Some(Frame::Pong(7))
+        ));
+        assert!(matches!(
+            codec.decode(&mut src).unwrap(),
+            Some(Frame::DatagramToken(u64::MAX))
+        ));
+        assert!(
+            matches!(codec.decode(&mut src).unwrap(), Some(Frame::Disconnect(reason)) if reason == "bye")
+        );
+        assert!(codec.decode(&mut src).unwrap().is_none());
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected() {
+        let codec = FrameCodec::new(8);
+
+        // Encoding refuses to produce the frame...
+        let mut dst = BytesMut::new();
+        assert!(codec
+            .encode(&Frame::Data(Bytes::from_static(b"too long!")), &mut dst)
+            .is_err());
+        assert!(dst.is_empty());
+
+        // ...and decoding rejects it as soon as the header arrives.
+        let mut src = encode_all(
+            &FrameCodec::default(),
+            &[Frame::Data(Bytes::from_static(b"too long!"))],
+        );
+        src.truncate(FRAME_HEADER_SIZE);
+        assert!(codec.decode(&mut src).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+// === Handshake === //
+
+// Bump this whenever the framing or handshake format changes in a way that the RPC schema
+// fingerprint wouldn't catch.
+pub const PROTOCOL_VERSION: u32 = 4;
+
+// The first frame sent by a client. Nothing else is processed until the server has replied with a
+// `HandshakeResponse`.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HandshakeHello {
+    pub protocol_version: u32,
+    pub schema_fingerprint: u64,
+}
+
+impl HandshakeHello {
+    pub fn new(schema_fingerprint: u64) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            schema_fingerprint,
+        }
+    }
+
+    pub fn validate(&self, expected: &HandshakeHello) -> Result<(), String> {
+        if self.protocol_version != expected.protocol_version {
+            return Err(format!(
+                "protocol version mismatch (client: {}, server: {})",
+                self.protocol_version, expected.protocol_version,
+            ));
+        }
+
+        if self.schema_fingerprint != expected.schema_fingerprint {
+            return Err(format!(
+                "RPC schema mismatch (client: {:016x}, server: {:016x}); is the client up to date?",
+                self.schema_fingerprint, expected.schema_fingerprint,
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum HandshakeResponse {
+    Accepted,
+    Rejected { reason: String },
+}
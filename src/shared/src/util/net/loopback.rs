@@ -0,0 +1,202 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc, time::Duration};
+
+use bytes::Bytes;
+use rustc_hash::FxHashMap;
+
+use super::transport::{
+    ClientTransport, QuadClientEvent, QuadPeerId, QuadServerEvent, ServerTransport,
+};
+
+// === Shared State === //
+
+// Packets are never delivered on their own. Each side only observes what the other side sent
+// once it calls `poll`, which lets tests pump the server and its clients in a deterministic order.
+#[derive(Debug, Default)]
+struct LoopbackState {
+    id_gen: u64,
+    server_alive: bool,
+    server_events: VecDeque<QuadServerEvent>,
+    clients: FxHashMap<QuadPeerId, LoopbackPeer>,
+}
+
+#[derive(Debug, Default)]
+struct LoopbackPeer {
+    connected: bool,
+    events: VecDeque<QuadClientEvent>,
+}
+
+impl LoopbackPeer {
+    fn close(&mut self, event: QuadClientEvent) -> bool {
+        if !self.connected {
+            return false;
+        }
+
+        self.connected = false;
+        self.events.push_back(event);
+        true
+    }
+}
+
+// === LoopbackServer === //
+
+#[derive(Debug)]
+pub struct LoopbackServer {
+    state: Rc<RefCell<LoopbackState>>,
+}
+
+impl Default for LoopbackServer {
+    fn default() -> Self {
+        Self {
+            state: Rc::new(RefCell::new(LoopbackState {
+                server_alive: true,
+                ..Default::default()
+            })),
+        }
+    }
+}
+
+impl LoopbackServer {
+    // The connector can be kept around after the server has been moved into a
+    // `DynServerTransport`.
+    pub fn connector(&self) -> LoopbackConnector {
+        LoopbackConnector {
+            state: self.state.clone(),
+        }
+    }
+
+    pub fn connect(&self) -> LoopbackClient {
+        self.connector().connect()
+    }
+}
+
+impl ServerTransport for LoopbackServer {
+    fn poll(&mut self) -> anyhow::Result<Vec<QuadServerEvent>> {
+        Ok(self.state.borrow_mut().server_events.drain(..).collect())
+    }
+
+    fn send(&mut self, id: QuadPeerId, data: Bytes) {
+        if let Some(peer) = self.state.borrow_mut().clients.get_mut(&id) {
+            if peer.connected {
+                peer.events.push_back(QuadClientEvent::Data(data));
+            }
+        }
+    }
+
+    fn rtt(&self, id: QuadPeerId) -> Option<Duration> {
+        let state = self.state.borrow();
+        let peer = state.clients.get(&id)?;
+        peer.connected.then_some(Duration::ZERO)
+    }
+
+    fn kick(&mut self, id: QuadPeerId, reason: &str) {
+        let state = &mut *self.state.borrow_mut();
+        let Some(peer) = state.clients.get_mut(&id) else {
+            return;
+        };
+
+        if peer.close(QuadClientEvent::Kicked(reason.to_string())) {
+            state
+                .server_events
+                .push_back(QuadServerEvent::PeerDisconnect {
+                    id,
+                    err: Some(anyhow::anyhow!("peer was kicked: {reason}")),
+                });
+        }
+    }
+}
+
+impl Drop for LoopbackServer {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        state.server_alive = false;
+
+        for peer in state.clients.values_mut() {
+            peer.close(QuadClientEvent::Disconnect(None));
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoopbackConnector {
+    state: Rc<RefCell<LoopbackState>>,
+}
+
+impl LoopbackConnector {
+    pub fn connect(&self) -> LoopbackClient {
+        let mut state = self.state.borrow_mut();
+        let id = QuadPeerId(state.id_gen);
+        state.id_gen += 1;
+
+        let mut peer = LoopbackPeer {
+            connected: true,
+            events: VecDeque::new(),
+        };
+
+        if state.server_alive {
+            state
+                .server_events
+                .push_back(QuadServerEvent::PeerConnected { id, addr: None });
+        } else {
+            peer.close(QuadClientEvent::Disconnect(None));
+        }
+
+        state.clients.insert(id, peer);
+
+        LoopbackClient {
+            state: self.state.clone(),
+            id,
+        }
+    }
+}
+
+// === LoopbackClient === //
+
+#[derive(Debug)]
+pub struct LoopbackClient {
+    state: Rc<RefCell<LoopbackState>>,
+    id: QuadPeerId,
+}
+
+impl LoopbackClient {
+    pub fn id(&self) -> QuadPeerId {
+        self.id
+    }
+}
+
+impl ClientTransport for LoopbackClient {
+    fn poll(&mut self) -> Vec<QuadClientEvent> {
+        self.state
+            .borrow_mut()
+            .clients
+            .get_mut(&self.id)
+            .map_or_else(Vec::new, |peer| peer.events.drain(..).collect())
+    }
+
+    fn send(&mut self, data: &[u8]) {
+        let mut state = self.state.borrow_mut();
+        if !state.clients[&self.id].connected {
+            return;
+        }
+
+        state.server_events.push_back(QuadServerEvent::PeerData {
+            id: self.id,
+            data: Bytes::copy_from_slice(data),
+        });
+    }
+}
+
+impl Drop for LoopbackClient {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        let peer = state.clients.remove(&self.id).unwrap();
+
+        if peer.connected {
+            state
+                .server_events
+                .push_back(QuadServerEvent::PeerDisconnect {
+                    id: self.id,
+                    err: None,
+                });
+        }
+    }
+}
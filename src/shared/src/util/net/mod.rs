@@ -0,0 +1,5 @@
+pub mod datagram;
+pub mod framing;
+pub mod handshake;
+pub mod loopback;
+pub mod transport;
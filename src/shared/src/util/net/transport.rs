@@ -0,0 +1,82 @@
+use std::{net::SocketAddr, time::Duration};
+
+use bytes::Bytes;
+
+// === Server === //
+
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub struct QuadPeerId(pub u64);
+
+#[derive(Debug)]
+pub enum QuadServerEvent {
+    PeerConnected {
+        id: QuadPeerId,
+        addr: Option<SocketAddr>,
+    },
+    PeerData {
+        id: QuadPeerId,
+        data: Bytes,
+    },
+    // Data sent with `ClientTransport::send_unreliable` which didn't go through the reliable
+    // stream. It may overtake data sent before it, including the peer's handshake.
+    PeerUnreliableData {
+        id: QuadPeerId,
+        data: Bytes,
+    },
+    PeerDisconnect {
+        id: QuadPeerId,
+        err: Option<anyhow::Error>,
+    },
+}
+
+pub type DynServerTransport = Box<dyn ServerTransport>;
+
+pub trait ServerTransport {
+    fn poll(&mut self) -> anyhow::Result<Vec<QuadServerEvent>>;
+
+    fn send(&mut self, id: QuadPeerId, data: Bytes);
+
+    // Sends data which may be dropped, duplicated, or reordered. Transports without an unreliable
+    // path just send it reliably.
+    fn send_unreliable(&mut self, id: QuadPeerId, data: Bytes) {
+        self.send(id, data);
+    }
+
+    // Returns the most recent round-trip time measurement for the peer, if any.
+    fn rtt(&self, id: QuadPeerId) -> Option<Duration>;
+
+    // Closes the connection once all previously sent data has been flushed, letting the peer know
+    // why it was disconnected. A `PeerDisconnect` event is produced for the peer once the connection
+    // has actually closed. Any data received from the peer in the meantime should be ignored.
+    fn kick(&mut self, id: QuadPeerId, reason: &str);
+}
+
+// === Client === //
+
+#[derive(Debug)]
+pub enum QuadClientEvent {
+    Data(Bytes),
+    // See `QuadServerEvent::PeerUnreliableData`.
+    UnreliableData(Bytes),
+    Kicked(String),
+    Disconnect(Option<anyhow::Error>),
+}
+
+impl QuadClientEvent {
+    pub fn is_disconnect(&self) -> bool {
+        matches!(self, Self::Kicked(_) | Self::Disconnect(_))
+    }
+}
+
+pub type DynClientTransport = Box<dyn ClientTransport>;
+
+pub trait ClientTransport {
+    fn poll(&mut self) -> Vec<QuadClientEvent>;
+
+    fn send(&mut self, data: &[u8]);
+
+    // See `ServerTransport::send_unreliable`.
+    fn send_unreliable(&mut self, data: &[u8]) {
+        self.send(data);
+    }
+}